@@ -0,0 +1,94 @@
+//! Compares the component storages on spawning and querying entities
+//! Run with `cargo bench`
+
+use gerust::{ArchetypeStorage, Component, Engine, SparseSet, Storage, StorageTrait};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ENTITIES: u32 = 10_000;
+const RUNS: u32 = 20;
+
+struct Position(f32, f32);
+impl Component for Position {}
+
+struct Velocity(f32, f32);
+impl Component for Velocity {}
+
+/// Only carried by a few entities, so that queries on it are selective
+struct Player;
+impl Component for Player {}
+
+fn new_engine<P, V, T>() -> Engine
+where
+    P: 'static + StorageTrait<Position>,
+    V: 'static + StorageTrait<Velocity>,
+    T: 'static + StorageTrait<Player>,
+{
+    let mut engine = Engine::headless();
+    engine.register_component_with_storage::<Position, P>();
+    engine.register_component_with_storage::<Velocity, V>();
+    engine.register_component_with_storage::<Player, T>();
+    engine
+}
+
+fn spawn(engine: &Engine) {
+    for i in 0..ENTITIES {
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Position(0.0, 0.0));
+        if i % 2 == 0 {
+            engine.add_entity_component(entity, Velocity(1.0, 1.0));
+        }
+        if i % 1000 == 0 {
+            engine.add_entity_component(entity, Player);
+        }
+    }
+}
+
+fn integrate(engine: &Engine) {
+    let mut query = engine.query::<(&mut Position, &Velocity)>();
+    for (_, (mut position, velocity)) in query.iter_mut() {
+        position.0 += velocity.0;
+        position.1 += velocity.1;
+    }
+}
+
+fn players(engine: &Engine) {
+    let query = engine.query::<(&Position, &Player)>();
+    for (_, (position, _)) in query.iter() {
+        black_box(position.0);
+    }
+}
+
+/// Average duration of f over RUNS runs
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+fn bench<P, V, T>(name: &str)
+where
+    P: 'static + StorageTrait<Position>,
+    V: 'static + StorageTrait<Velocity>,
+    T: 'static + StorageTrait<Player>,
+{
+    let spawn_time = time(|| spawn(&new_engine::<P, V, T>()));
+    let engine = new_engine::<P, V, T>();
+    spawn(&engine);
+    let integrate_time = time(|| integrate(&engine));
+    let players_time = time(|| players(&engine));
+    println!(
+        "{:<16} spawn {:>12?}  integrate {:>12?}  players {:>12?}",
+        name, spawn_time, integrate_time, players_time
+    );
+}
+
+fn main() {
+    bench::<Storage<Position>, Storage<Velocity>, Storage<Player>>("Storage");
+    bench::<SparseSet<Position>, SparseSet<Velocity>, SparseSet<Player>>("SparseSet");
+    bench::<ArchetypeStorage<Position>, ArchetypeStorage<Velocity>, ArchetypeStorage<Player>>(
+        "ArchetypeStorage",
+    );
+}
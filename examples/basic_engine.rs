@@ -1,99 +1,35 @@
 use gerust::*;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::surface::Surface;
 
 const SQUARE_SIZE: u32 = 25;
-const MAX_VELOCITY: i32 = 25;
+/// Acceleration of movable squares, in pixels per second squared
+const GRAVITY: f32 = 3600.;
+/// Maximum falling speed, in pixels per second
+const MAX_VELOCITY: f32 = 1500.;
 
-#[derive(Debug, PartialEq, Eq)]
-struct Position {
-    x: i32,
-    y: i32,
-}
-
-impl Component for Position {}
-impl Position {
-    fn new(x: i32, y: i32) -> Position {
-        Position { x, y }
-    }
-}
+/// Marker of the squares affected by gravity
+struct Movable;
+impl Component for Movable {}
 
-#[derive(Debug, PartialEq, Eq)]
-struct Velocity {
-    x: i32,
-    y: i32,
-    is_movable: bool,
-}
+/// Texture shared by every square
+struct SquareTexture(TextureHandle);
 
-impl Component for Velocity {}
-impl Velocity {
-    fn new(is_movable: bool) -> Velocity {
-        Velocity {
-            x: 0,
-            y: 0,
-            is_movable,
-        }
-    }
+fn square(texture: TextureHandle) -> Sprite {
+    Sprite::new(texture).with_tint(Color::BLACK)
 }
 
-struct Gravity;
-impl System for Gravity {
-    fn update(&self, engine: &Engine, _: &[Event]) -> Result<UpdateStatus, String> {
-        let mask = engine.get_mask::<Velocity>();
-        let mut velocities = engine.get_component::<Velocity>();
-
-        for (entity, _) in engine
-            .entities
-            .borrow()
-            .iter()
-            .filter(|(_, entity)| entity.components_mask() & mask != 0)
-        {
-            let velocity = velocities.get_mut(*entity);
-            if velocity.is_movable && velocity.y < MAX_VELOCITY {
-                velocity.y += 1;
-            }
-        }
-        Ok(UpdateStatus::Continue)
-    }
-}
-
-struct Render;
-impl System for Render {
-    fn update(&self, engine: &Engine, _: &[Event]) -> Result<UpdateStatus, String> {
-        let mask = engine.get_mask::<Position>();
-        let positions = engine.get_component::<Position>();
-        let mut canvas = engine.canvas.borrow_mut();
-
-        canvas.set_draw_color(Color::RGB(0, 255, 255));
-        canvas.clear();
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-
-        for (entity, _) in engine
-            .entities
-            .borrow()
-            .iter()
-            .filter(|(_, entity)| entity.components_mask() & mask == mask)
-        {
-            let entity_pos = positions.get(*entity);
-            canvas
-                .fill_rect(Rect::new(
-                    entity_pos.x as i32,
-                    entity_pos.y as i32,
-                    SQUARE_SIZE,
-                    SQUARE_SIZE,
-                ))
-                .unwrap();
-        }
-        canvas.present();
-        Ok(UpdateStatus::Continue)
+fn gravity(time: Res<Time>, mut query: Query<&mut Velocity2D, With<Movable>>) {
+    for (_, mut velocity) in query.iter_mut() {
+        velocity.linear.y = (velocity.linear.y + GRAVITY * time.delta_secs()).min(MAX_VELOCITY);
     }
 }
 
 struct Exit;
 impl System for Exit {
-    fn update(&self, _: &Engine, events: &[Event]) -> Result<UpdateStatus, String> {
+    fn update(&self, _: &Engine, events: &[Event]) -> Result<UpdateStatus, GerustError> {
         for event in events {
             match event {
                 Event::Quit { .. }
@@ -108,71 +44,53 @@ impl System for Exit {
     }
 }
 
-struct SpawnOnClick;
-impl System for SpawnOnClick {
-    fn update(&self, engine: &Engine, events: &[Event]) -> Result<UpdateStatus, String> {
-        for event in events {
-            match event {
-                Event::MouseButtonDown { x, y, .. } => {
-                    let entity = engine.create_entity();
-                    engine.add_entity_component(entity, Position::new(*x, *y));
-                    engine.add_entity_component(entity, Velocity::new(true));
-                }
-                _ => {}
-            }
-        }
-        Ok(UpdateStatus::Continue)
-    }
-}
-
-struct ApplyVelocity;
-impl System for ApplyVelocity {
-    fn update(&self, engine: &Engine, _: &[Event]) -> Result<UpdateStatus, String> {
-        let mask = engine.get_mask::<Position>() | engine.get_mask::<Velocity>();
-        let mut positions = engine.get_component::<Position>();
-        let velocities = engine.get_component::<Velocity>();
-
-        for (entity, _) in engine
-            .entities
-            .borrow()
-            .iter()
-            .filter(|(_, entity)| entity.components_mask() & mask == mask)
-        {
-            let entity_pos = positions.get_mut(*entity);
-            let entity_velocity = velocities.get(*entity);
-            entity_pos.x += entity_velocity.x;
-            entity_pos.y += entity_velocity.y;
+fn spawn_on_click(
+    events: Res<InputEvents>,
+    texture: Res<SquareTexture>,
+    cameras: Query<&Camera2D>,
+    commands: Commands,
+) {
+    for event in events.iter() {
+        if let Event::MouseButtonDown { x, y, .. } = event {
+            let click = Vec2::new(*x as f32, *y as f32);
+            let camera = match cameras.iter().find(|(_, camera)| camera.contains(click)) {
+                Some((_, camera)) => camera,
+                None => continue,
+            };
+            let entity = commands.spawn();
+            commands.insert(
+                entity,
+                Transform2D::from_translation(camera.screen_to_world(click)),
+            );
+            commands.insert(entity, Velocity2D::default());
+            commands.insert(entity, Movable);
+            commands.insert(entity, square(texture.0));
         }
-        Ok(UpdateStatus::Continue)
     }
 }
 
-/// Collider for our engine, is run after ApplyVelocity and fixes any colliding rectangle
+/// Collider for our engine, is run after integrate_velocity and fixes any colliding rectangle
 struct Collision;
 impl System for Collision {
-    fn update(&self, engine: &Engine, _: &[Event]) -> Result<UpdateStatus, String> {
-        let mask = engine.get_mask::<Position>() | engine.get_mask::<Velocity>();
-        let mut positions = engine.get_component::<Position>();
-        let mut velocities = engine.get_component::<Velocity>();
-
-        let entities_ref = engine.entities.borrow();
-        let entities: Vec<&u64> = entities_ref
-            .iter()
-            .filter(|(_, entity)| entity.components_mask() & mask == mask)
-            .map(|(entity_id, _)| entity_id)
-            .collect();
-        for i in 0..entities.len() as u64 {
-            for j in (i + 1)..entities.len() as u64 {
-                let square1 = positions.get(i);
-                let square2 = positions.get(j);
-                if is_colliding(square1, square2) {
-                    if velocities.get_mut(i).y < velocities.get_mut(j).y {
-                        positions.get_mut(j).y = square1.y - SQUARE_SIZE as i32;
-                        velocities.get_mut(j).y = 0;
+    fn update(&self, engine: &Engine, _: &[Event]) -> Result<UpdateStatus, GerustError> {
+        let mut query = engine.query::<(&mut Transform2D, &mut Velocity2D)>();
+        let entities = query.entities().to_vec();
+
+        for (i, &entity1) in entities.iter().enumerate() {
+            for &entity2 in entities.iter().skip(i + 1) {
+                let (square1, velocity1) = query.get_mut(entity1).unwrap();
+                let (square1, velocity1) = (square1.translation, velocity1.linear.y);
+                let (square2, velocity2) = query.get_mut(entity2).unwrap();
+                let (square2, velocity2) = (square2.translation, velocity2.linear.y);
+                if is_colliding(&square1, &square2) {
+                    let (entity, above) = if velocity1 < velocity2 {
+                        (entity2, square1)
                     } else {
-                        positions.get_mut(i).y = square2.y - SQUARE_SIZE as i32;
-                        velocities.get_mut(i).y = 0;
-                    }
+                        (entity1, square2)
+                    };
+                    let (mut transform, mut velocity) = query.get_mut(entity).unwrap();
+                    transform.translation.y = above.y - SQUARE_SIZE as f32;
+                    velocity.linear.y = 0.;
                 }
             }
         }
@@ -181,8 +99,8 @@ impl System for Collision {
     }
 }
 
-fn is_colliding(square1: &Position, square2: &Position) -> bool {
-    let size = SQUARE_SIZE as i32;
+fn is_colliding(square1: &Vec2, square2: &Vec2) -> bool {
+    let size = SQUARE_SIZE as f32;
     square1.x < square2.x + size
         && square1.x + size > square2.x
         && square1.y < square2.y + size
@@ -192,28 +110,39 @@ fn is_colliding(square1: &Position, square2: &Position) -> bool {
 fn main() {
     let mut engine = Engine::new("Basic Engine", 640, 480).expect("Could not initialize engine");
 
-    engine.register_component::<Position>();
-    engine.register_component::<Velocity>();
+    engine.register_component::<Movable>();
+    engine.insert_resource(ClearColor(Color::RGB(0, 255, 255)));
 
-    let entity1 = engine.create_entity();
-    engine.add_entity_component(entity1, Position::new(100, 1000));
-    engine.add_entity_component(entity1, Velocity::new(true));
-    let entity2 = engine.create_entity();
-    engine.add_entity_component(entity2, Position::new(56, 800));
-    engine.add_entity_component(entity2, Velocity::new(true));
+    let mut surface = Surface::new(SQUARE_SIZE, SQUARE_SIZE, PixelFormatEnum::RGBA8888)
+        .expect("Could not create square surface");
+    surface.fill_rect(None, Color::WHITE).unwrap();
+    let texture = engine
+        .non_send_resource_mut::<Textures>()
+        .add_surface(&surface)
+        .expect("Could not create square texture");
+    engine.insert_resource(SquareTexture(texture));
+
+    for (x, y) in [(100., 1000.), (56., 800.)] {
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Transform2D::from_xy(x, y));
+        engine.add_entity_component(entity, Velocity2D::default());
+        engine.add_entity_component(entity, Movable);
+        engine.add_entity_component(entity, square(texture));
+    }
 
     // Add bottom row if immovable objects
+    let half_size = SQUARE_SIZE as f32 / 2.;
     for i in 0..(640 / SQUARE_SIZE) {
+        let x = (i * SQUARE_SIZE) as f32 + half_size;
         let entity = engine.create_entity();
-        engine.add_entity_component(entity, Position::new((i * SQUARE_SIZE) as i32, 470));
-        engine.add_entity_component(entity, Velocity::new(false));
+        engine.add_entity_component(entity, Transform2D::from_xy(x, 470. + half_size));
+        engine.add_entity_component(entity, Velocity2D::default());
+        engine.add_entity_component(entity, square(texture));
     }
 
-    engine.register_system(Exit {});
-    engine.register_system(SpawnOnClick {});
-    engine.register_system(Gravity {});
-    engine.register_system(ApplyVelocity {});
-    engine.register_system(Collision {});
-    engine.register_system(Render {});
+    engine.register_system(Exit {}.in_stage(Stage::PreUpdate));
+    engine.register_system(spawn_on_click.in_stage(Stage::PreUpdate));
+    engine.register_system(Collision {}.after("integrate_velocity"));
+    engine.register_system(gravity.before("integrate_velocity"));
     engine.run().expect("Could not run engine");
 }
@@ -0,0 +1,205 @@
+use std::any::{self, TypeId};
+use std::collections::HashMap;
+
+/// A piece of data a system can access
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum DataId {
+    Component(TypeId),
+    Resource(TypeId),
+}
+
+/// Components and resources a system reads and writes
+/// Systems with compatible accesses can't run into borrow conflicts with each other
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    /// Whether the system may access anything in the Engine
+    exclusive: bool,
+    /// Whether the system uses data that can't leave the main thread
+    main_thread: bool,
+    /// Whether the system records commands, applied before the next systems run
+    commands: bool,
+    reads: HashMap<DataId, &'static str>,
+    writes: HashMap<DataId, &'static str>,
+}
+
+impl Access {
+    /// Create an Access to nothing
+    pub fn new() -> Access {
+        Access::default()
+    }
+
+    /// Create an Access to everything, the default for System implementations
+    pub fn exclusive() -> Access {
+        Access {
+            exclusive: true,
+            ..Access::default()
+        }
+    }
+
+    /// Check if the Access covers the whole Engine
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    /// Check if the system must run on the main thread
+    pub fn is_main_thread(&self) -> bool {
+        self.exclusive || self.main_thread
+    }
+
+    /// Record that the system uses data that can't leave the main thread
+    pub fn set_main_thread(&mut self) {
+        self.main_thread = true;
+    }
+
+    /// Check if the system records commands
+    pub fn has_commands(&self) -> bool {
+        self.exclusive || self.commands
+    }
+
+    /// Record that the system records commands
+    pub fn set_commands(&mut self) {
+        self.commands = true;
+    }
+
+    fn add_read(&mut self, id: DataId, name: &'static str) {
+        self.reads.insert(id, name);
+    }
+
+    fn add_write(&mut self, id: DataId, name: &'static str) {
+        self.writes.insert(id, name);
+    }
+
+    /// Record that component T is read
+    pub fn add_component_read<T: 'static>(&mut self) {
+        self.add_read(DataId::Component(TypeId::of::<T>()), any::type_name::<T>());
+    }
+
+    /// Record that component T is written
+    pub fn add_component_write<T: 'static>(&mut self) {
+        self.add_write(DataId::Component(TypeId::of::<T>()), any::type_name::<T>());
+    }
+
+    /// Record that resource R is read
+    pub fn add_resource_read<R: 'static>(&mut self) {
+        self.add_read(DataId::Resource(TypeId::of::<R>()), any::type_name::<R>());
+    }
+
+    /// Record that resource R is written
+    pub fn add_resource_write<R: 'static>(&mut self) {
+        self.add_write(DataId::Resource(TypeId::of::<R>()), any::type_name::<R>());
+    }
+
+    /// Check if component T is read or written
+    pub fn has_component<T: 'static>(&self) -> bool {
+        let id = DataId::Component(TypeId::of::<T>());
+        self.exclusive || self.reads.contains_key(&id) || self.writes.contains_key(&id)
+    }
+
+    /// Check if component T is written
+    pub fn has_component_write<T: 'static>(&self) -> bool {
+        self.exclusive
+            || self
+                .writes
+                .contains_key(&DataId::Component(TypeId::of::<T>()))
+    }
+
+    /// Check if resource R is read or written
+    pub fn has_resource<R: 'static>(&self) -> bool {
+        let id = DataId::Resource(TypeId::of::<R>());
+        self.exclusive || self.reads.contains_key(&id) || self.writes.contains_key(&id)
+    }
+
+    /// Check if resource R is written
+    pub fn has_resource_write<R: 'static>(&self) -> bool {
+        self.exclusive
+            || self
+                .writes
+                .contains_key(&DataId::Resource(TypeId::of::<R>()))
+    }
+
+    /// Add every access of other to self
+    pub fn extend(&mut self, other: &Access) {
+        self.exclusive |= other.exclusive;
+        self.main_thread |= other.main_thread;
+        self.commands |= other.commands;
+        self.reads.extend(other.reads.iter());
+        self.writes.extend(other.writes.iter());
+    }
+
+    /// Names of the data written by one Access and read or written by the other
+    /// An exclusive Access conflicts with everything, reported as "Engine"
+    pub fn conflicts(&self, other: &Access) -> Vec<&'static str> {
+        if self.exclusive || other.exclusive {
+            return vec!["Engine"];
+        }
+        let mut conflicts: Vec<&'static str> = self
+            .writes
+            .iter()
+            .filter(|(id, _)| other.reads.contains_key(id) || other.writes.contains_key(id))
+            .chain(
+                other
+                    .writes
+                    .iter()
+                    .filter(|(id, _)| self.reads.contains_key(id)),
+            )
+            .map(|(_, name)| *name)
+            .collect();
+        conflicts.sort_unstable();
+        conflicts.dedup();
+        conflicts
+    }
+
+    /// Check if two accesses can be held at the same time
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        self.conflicts(other).is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Position;
+    struct Velocity;
+
+    #[test]
+    fn reads_are_compatible() {
+        let mut access1 = Access::new();
+        access1.add_component_read::<Position>();
+        let mut access2 = Access::new();
+        access2.add_component_read::<Position>();
+        access2.add_component_write::<Velocity>();
+
+        assert!(access1.is_compatible(&access2));
+        assert!(access2.has_component::<Position>());
+        assert!(!access2.has_component_write::<Position>());
+    }
+
+    #[test]
+    fn write_conflicts() {
+        let mut access1 = Access::new();
+        access1.add_component_read::<Position>();
+        access1.add_component_write::<Velocity>();
+        let mut access2 = Access::new();
+        access2.add_component_write::<Position>();
+        access2.add_component_read::<Velocity>();
+
+        assert!(access1.conflicts(&access2).len() == 2);
+        assert!(access2.conflicts(&access1).len() == 2);
+    }
+
+    #[test]
+    fn components_and_resources_are_distinct() {
+        let mut access1 = Access::new();
+        access1.add_component_write::<Position>();
+        let mut access2 = Access::new();
+        access2.add_resource_write::<Position>();
+
+        assert!(access1.is_compatible(&access2));
+    }
+
+    #[test]
+    fn exclusive_conflicts() {
+        assert!(Access::exclusive().conflicts(&Access::new()) == ["Engine"]);
+    }
+}
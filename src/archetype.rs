@@ -0,0 +1,393 @@
+use super::{
+    Component, ComponentMask, ComponentTicks, EntityIndex, GerustError, StorageTrait, Tick,
+};
+use crate::storage::missing_component;
+use std::collections::HashMap;
+use std::mem;
+use std::ptr::NonNull;
+
+/// Identifies a set of components, entities having exactly the same
+/// components share the same archetype
+pub type ArchetypeId = usize;
+
+/// Archetype of the entities without any component
+pub const EMPTY_ARCHETYPE: ArchetypeId = 0;
+
+/// Every entity having exactly the components of mask
+pub(crate) struct Archetype {
+    mask: ComponentMask,
+    entities: Vec<EntityIndex>,
+}
+
+impl Archetype {
+    pub(crate) fn entities(&self) -> &[EntityIndex] {
+        &self.entities
+    }
+}
+
+/// Index of the entities by component set, so queries only visit the
+/// archetypes they match instead of every entity
+pub(crate) struct Archetypes {
+    archetypes: Vec<Archetype>,
+    ids: HashMap<ComponentMask, ArchetypeId>,
+    /// Archetype and row of every entity
+    locations: HashMap<EntityIndex, (ArchetypeId, usize)>,
+}
+
+impl Archetypes {
+    pub(crate) fn new() -> Archetypes {
+        let mut archetypes = Archetypes {
+            archetypes: vec![],
+            ids: HashMap::new(),
+            locations: HashMap::new(),
+        };
+        archetypes.get_or_create(&ComponentMask::new());
+        archetypes
+    }
+
+    fn get_or_create(&mut self, mask: &ComponentMask) -> ArchetypeId {
+        if let Some(id) = self.ids.get(mask) {
+            return *id;
+        }
+        let id = self.archetypes.len();
+        self.archetypes.push(Archetype {
+            mask: mask.clone(),
+            entities: vec![],
+        });
+        self.ids.insert(mask.clone(), id);
+        id
+    }
+
+    /// Add a new entity without any component
+    pub(crate) fn insert(&mut self, entity: EntityIndex) {
+        self.push(entity, EMPTY_ARCHETYPE);
+    }
+
+    /// Remove an entity, returning the archetype it was in
+    pub(crate) fn remove(&mut self, entity: EntityIndex) -> Option<ArchetypeId> {
+        let (archetype, row) = self.locations.remove(&entity)?;
+        let entities = &mut self.archetypes[archetype].entities;
+        entities.swap_remove(row);
+        // The last entity of the archetype now fills the hole
+        if let Some(moved) = entities.get(row) {
+            self.locations.insert(*moved, (archetype, row));
+        }
+        Some(archetype)
+    }
+
+    /// Move an entity to the archetype of mask, returning the new archetype
+    pub(crate) fn move_entity(&mut self, entity: EntityIndex, mask: &ComponentMask) -> ArchetypeId {
+        let archetype = self.get_or_create(mask);
+        self.remove(entity);
+        self.push(entity, archetype);
+        archetype
+    }
+
+    fn push(&mut self, entity: EntityIndex, archetype: ArchetypeId) {
+        let entities = &mut self.archetypes[archetype].entities;
+        self.locations.insert(entity, (archetype, entities.len()));
+        entities.push(entity);
+    }
+
+    /// Get every archetype having the required components and none of the excluded ones
+    pub(crate) fn matching<'a>(
+        &'a self,
+        required: &'a ComponentMask,
+        excluded: &'a ComponentMask,
+    ) -> impl Iterator<Item = &'a Archetype> {
+        self.archetypes.iter().filter(move |archetype| {
+            required.is_subset(&archetype.mask) && !excluded.intersects(&archetype.mask)
+        })
+    }
+}
+
+/// Components of a single archetype, packed in the same order as its entities
+struct Column<T> {
+    entities: Vec<EntityIndex>,
+    components: Vec<T>,
+    ticks: Vec<ComponentTicks>,
+}
+
+/// Component storage organised as one column per archetype
+/// Entities sharing the same components are stored next to each other, in the
+/// same order in every archetype storage, making the archetype a table
+pub struct ArchetypeStorage<T: Component> {
+    columns: Vec<Column<T>>,
+
+    /// Archetype and row of the component of each entity slot
+    locations: Vec<Option<(ArchetypeId, usize)>>,
+
+    /// Archetype the Engine last placed each entity slot in
+    archetypes: Vec<ArchetypeId>,
+}
+
+impl<T: Component> ArchetypeStorage<T> {
+    fn location(&self, index: EntityIndex) -> Option<(ArchetypeId, usize)> {
+        let (archetype, row) = (*self.locations.get(index.index() as usize)?)?;
+        // Rows keep the generation of their entity, older handles of the slot don't match it
+        if self.columns[archetype].entities[row] == index {
+            Some((archetype, row))
+        } else {
+            None
+        }
+    }
+
+    fn column(&mut self, archetype: ArchetypeId) -> &mut Column<T> {
+        while self.columns.len() <= archetype {
+            self.columns.push(Column {
+                entities: vec![],
+                components: vec![],
+                ticks: vec![],
+            });
+        }
+        &mut self.columns[archetype]
+    }
+
+    fn push(&mut self, index: EntityIndex, component: T, ticks: ComponentTicks) {
+        let slot = index.index() as usize;
+        let archetype = self
+            .archetypes
+            .get(slot)
+            .copied()
+            .unwrap_or(EMPTY_ARCHETYPE);
+        let column = self.column(archetype);
+        let row = column.entities.len();
+        column.entities.push(index);
+        column.components.push(component);
+        column.ticks.push(ticks);
+        if self.locations.len() <= slot {
+            self.locations.resize(slot + 1, None);
+        }
+        self.locations[slot] = Some((archetype, row));
+    }
+
+    /// Remove an entity component along with its ticks
+    fn take(&mut self, index: EntityIndex) -> Option<(T, ComponentTicks)> {
+        let (archetype, row) = self.location(index)?;
+        self.locations[index.index() as usize] = None;
+        let column = &mut self.columns[archetype];
+        column.entities.swap_remove(row);
+        let component = column.components.swap_remove(row);
+        let ticks = column.ticks.swap_remove(row);
+        // swap_remove moved the last row here, its entity must be found at its new row
+        if let Some(moved) = column.entities.get(row) {
+            self.locations[moved.index() as usize] = Some((archetype, row));
+        }
+        Some((component, ticks))
+    }
+}
+
+impl<T: Component> StorageTrait<T> for ArchetypeStorage<T> {
+    fn new() -> ArchetypeStorage<T> {
+        ArchetypeStorage {
+            columns: vec![],
+            locations: vec![],
+            archetypes: vec![],
+        }
+    }
+
+    fn add_entity_with_ticks(&mut self, index: EntityIndex, component: T, ticks: ComponentTicks) {
+        self.take(index);
+        self.push(index, component, ticks);
+    }
+
+    fn remove_entity(&mut self, index: EntityIndex) -> Option<T> {
+        self.take(index).map(|(component, _)| component)
+    }
+
+    fn try_get(&self, index: EntityIndex) -> Result<&T, GerustError> {
+        match self.location(index) {
+            Some((archetype, row)) => Ok(&self.columns[archetype].components[row]),
+            None => Err(missing_component::<T>(index)),
+        }
+    }
+
+    fn try_get_mut(&mut self, index: EntityIndex) -> Result<&mut T, GerustError> {
+        match self.location(index) {
+            Some((archetype, row)) => Ok(&mut self.columns[archetype].components[row]),
+            None => Err(missing_component::<T>(index)),
+        }
+    }
+
+    fn ticks(&self, index: EntityIndex) -> Option<ComponentTicks> {
+        let (archetype, row) = self.location(index)?;
+        Some(self.columns[archetype].ticks[row])
+    }
+
+    fn get_ptr(&mut self, index: EntityIndex) -> Option<(NonNull<T>, NonNull<ComponentTicks>)> {
+        let (archetype, row) = self.location(index)?;
+        let column = &mut self.columns[archetype];
+        // Safety: row is in bounds of both Vecs, and as_mut_ptr doesn't borrow the other components
+        unsafe {
+            Some((
+                NonNull::new_unchecked(column.components.as_mut_ptr().add(row)),
+                NonNull::new_unchecked(column.ticks.as_mut_ptr().add(row)),
+            ))
+        }
+    }
+
+    fn clamp_ticks(&mut self, change_tick: Tick) {
+        for ticks in self.columns.iter_mut().flat_map(|column| &mut column.ticks) {
+            ticks.clamp(change_tick);
+        }
+    }
+
+    fn contains(&self, index: EntityIndex) -> bool {
+        self.location(index).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.columns
+            .iter()
+            .map(|column| column.entities.len())
+            .sum()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (EntityIndex, &T)> + '_> {
+        Box::new(self.columns.iter().flat_map(|column| {
+            column
+                .entities
+                .iter()
+                .copied()
+                .zip(column.components.iter())
+        }))
+    }
+
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (EntityIndex, &mut T)> + '_> {
+        Box::new(self.columns.iter_mut().flat_map(|column| {
+            column
+                .entities
+                .iter()
+                .copied()
+                .zip(column.components.iter_mut())
+        }))
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (EntityIndex, T)> + '_> {
+        self.locations.clear();
+        Box::new(
+            mem::take(&mut self.columns)
+                .into_iter()
+                .flat_map(|column| column.entities.into_iter().zip(column.components)),
+        )
+    }
+
+    fn tracks_archetypes() -> bool {
+        true
+    }
+
+    fn move_to_archetype(&mut self, index: EntityIndex, archetype: ArchetypeId) {
+        let slot = index.index() as usize;
+        if self.archetypes.len() <= slot {
+            self.archetypes.resize(slot + 1, EMPTY_ARCHETYPE);
+        }
+        self.archetypes[slot] = archetype;
+        if let Some((component, ticks)) = self.take(index) {
+            self.push(index, component, ticks);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    impl Component for Health {}
+
+    #[test]
+    fn move_entity() {
+        let mut archetypes = Archetypes::new();
+        let entity1 = EntityIndex::new(0, 0);
+        let entity2 = EntityIndex::new(1, 0);
+        archetypes.insert(entity1);
+        archetypes.insert(entity2);
+
+        let archetype = archetypes.move_entity(entity1, &ComponentMask::with_bit(2));
+
+        assert!(archetypes.archetypes.len() == 2);
+        assert!(archetypes.archetypes[EMPTY_ARCHETYPE].entities() == [entity2]);
+        assert!(archetypes.archetypes[archetype].entities() == [entity1]);
+        assert!(archetypes.locations[&entity2] == (EMPTY_ARCHETYPE, 0));
+    }
+
+    #[test]
+    fn matching() {
+        let mut archetypes = Archetypes::new();
+        let entity1 = EntityIndex::new(0, 0);
+        let entity2 = EntityIndex::new(1, 0);
+        archetypes.insert(entity1);
+        archetypes.insert(entity2);
+        let both = ComponentMask::with_bit(0) | ComponentMask::with_bit(1);
+        archetypes.move_entity(entity1, &ComponentMask::with_bit(0));
+        archetypes.move_entity(entity2, &both);
+
+        let matched: Vec<EntityIndex> = archetypes
+            .matching(&ComponentMask::with_bit(0), &ComponentMask::with_bit(1))
+            .flat_map(|archetype| archetype.entities().iter().copied())
+            .collect();
+
+        assert!(matched == [entity1]);
+    }
+
+    #[test]
+    fn remove_entity() {
+        let mut archetypes = Archetypes::new();
+        let entity = EntityIndex::new(0, 0);
+        archetypes.insert(entity);
+
+        assert!(archetypes.remove(entity) == Some(EMPTY_ARCHETYPE));
+        assert!(archetypes.remove(entity).is_none());
+        assert!(archetypes.archetypes[EMPTY_ARCHETYPE].entities().is_empty());
+    }
+
+    #[test]
+    fn storage_follows_archetype() {
+        let mut storage: ArchetypeStorage<Health> = ArchetypeStorage::new();
+        let entity1 = EntityIndex::new(0, 0);
+        let entity2 = EntityIndex::new(1, 0);
+        storage.move_to_archetype(entity1, 1);
+        storage.add_entity(entity1, Health(1));
+        storage.move_to_archetype(entity2, 1);
+        storage.add_entity(entity2, Health(2));
+
+        storage.move_to_archetype(entity1, 2);
+
+        assert!(storage.columns[1].components == [Health(2)]);
+        assert!(storage.columns[2].components == [Health(1)]);
+        assert!(storage.get(entity1) == &Health(1));
+        assert!(storage.get(entity2) == &Health(2));
+    }
+
+    #[test]
+    fn storage_remove_entity() {
+        let mut storage: ArchetypeStorage<Health> = ArchetypeStorage::new();
+        let entity1 = EntityIndex::new(0, 0);
+        let entity2 = EntityIndex::new(1, 0);
+        storage.add_entity(entity1, Health(1));
+        storage.add_entity(entity2, Health(2));
+
+        assert!(storage.remove_entity(entity1) == Some(Health(1)));
+
+        assert!(storage.try_get(entity1).is_err());
+        assert!(storage.get(entity2) == &Health(2));
+        assert!(storage.try_get(EntityIndex::new(1, 1)).is_err());
+    }
+
+    #[test]
+    fn storage_iterates_by_archetype() {
+        let mut storage: ArchetypeStorage<Health> = ArchetypeStorage::new();
+        let entity1 = EntityIndex::new(0, 0);
+        let entity2 = EntityIndex::new(1, 0);
+        storage.move_to_archetype(entity1, 2);
+        storage.add_entity(entity1, Health(1));
+        storage.add_entity(entity2, Health(2));
+
+        assert!(storage.len() == 2);
+        assert!(storage.entities().collect::<Vec<_>>() == [entity2, entity1]);
+        assert!(storage.drain().map(|(_, health)| health.0).sum::<u32>() == 3);
+        assert!(storage.is_empty());
+        assert!(!storage.contains(entity1));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{Component, GlobalTransform2D, Vec2};
+use sdl2::rect::Rect;
+
+/// View of the world drawn by render_sprites into a part of the canvas
+/// Engine::new spawns one covering the window, add more for split-screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera2D {
+    /// World point shown at the center of the viewport
+    pub position: Vec2,
+    /// Counterclockwise rotation of the view, in radians
+    pub rotation: f32,
+    /// Screen pixels per world unit, must not be zero
+    pub zoom: f32,
+    /// Part of the canvas the camera draws to, in pixels
+    pub viewport: Rect,
+    /// Cameras of higher order draw over the ones of lower order
+    pub order: i32,
+}
+
+impl Camera2D {
+    /// Camera drawing to viewport, where world coordinates match screen pixels
+    pub fn new(viewport: Rect) -> Camera2D {
+        Camera2D {
+            position: Vec2::new(viewport.center().x() as f32, viewport.center().y() as f32),
+            rotation: 0.,
+            zoom: 1.,
+            viewport,
+            order: 0,
+        }
+    }
+
+    pub fn with_position(self, position: Vec2) -> Camera2D {
+        Camera2D { position, ..self }
+    }
+
+    pub fn with_rotation(self, rotation: f32) -> Camera2D {
+        Camera2D { rotation, ..self }
+    }
+
+    pub fn with_zoom(self, zoom: f32) -> Camera2D {
+        Camera2D { zoom, ..self }
+    }
+
+    pub fn with_order(self, order: i32) -> Camera2D {
+        Camera2D { order, ..self }
+    }
+
+    fn viewport_center(&self) -> Vec2 {
+        let center = self.viewport.center();
+        Vec2::new(center.x() as f32, center.y() as f32)
+    }
+
+    /// Transform from world coordinates to screen pixels
+    pub fn view_transform(&self) -> GlobalTransform2D {
+        let x_axis = Vec2::X.rotate(-self.rotation) * self.zoom;
+        let y_axis = Vec2::Y.rotate(-self.rotation) * self.zoom;
+        let translation =
+            self.viewport_center() - (x_axis * self.position.x + y_axis * self.position.y);
+        GlobalTransform2D {
+            x_axis,
+            y_axis,
+            translation,
+        }
+    }
+
+    /// Screen pixel where a world point is drawn, possibly outside of the viewport
+    pub fn world_to_screen(&self, point: Vec2) -> Vec2 {
+        (point - self.position).rotate(-self.rotation) * self.zoom + self.viewport_center()
+    }
+
+    /// World point drawn at a screen pixel, e.g. the one of a mouse event
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        ((point - self.viewport_center()) / self.zoom).rotate(self.rotation) + self.position
+    }
+
+    /// Check if a screen pixel is inside the viewport
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.viewport
+            .contains_point((point.x.floor() as i32, point.y.floor() as i32))
+    }
+}
+
+impl Component for Camera2D {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn approx_eq(vec1: Vec2, vec2: Vec2) -> bool {
+        vec1.distance(vec2) < 1e-3
+    }
+
+    #[test]
+    fn default_view() {
+        let camera = Camera2D::new(Rect::new(0, 0, 640, 480));
+        let point = Vec2::new(12., 34.);
+
+        assert!(approx_eq(camera.world_to_screen(point), point));
+        assert!(approx_eq(camera.screen_to_world(point), point));
+        assert!(camera.view_transform() == GlobalTransform2D::IDENTITY);
+    }
+
+    #[test]
+    fn zoomed_and_rotated() {
+        let camera = Camera2D::new(Rect::new(320, 0, 320, 480))
+            .with_position(Vec2::new(100., 100.))
+            .with_rotation(FRAC_PI_2)
+            .with_zoom(2.);
+        let point = Vec2::new(110., 100.);
+
+        let screen = camera.world_to_screen(point);
+
+        // The world turns the opposite way of the camera
+        assert!(approx_eq(screen, Vec2::new(480., 220.)));
+        assert!(approx_eq(
+            camera.view_transform().transform_point(point),
+            screen
+        ));
+        assert!(approx_eq(camera.screen_to_world(screen), point));
+        assert!(camera.contains(screen));
+        assert!(!camera.contains(Vec2::new(100., 220.)));
+    }
+}
@@ -0,0 +1,326 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Borrow count of a cell being mutably borrowed
+const WRITING: usize = usize::MAX;
+
+/// A RefCell whose borrow tracking is atomic, so it can be shared between threads
+/// Borrows never block, a conflicting borrow fails just like with a RefCell
+pub struct AtomicRefCell<T: ?Sized> {
+    borrows: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+// The borrow tracking ensures a value is never accessed mutably while shared
+unsafe impl<T: ?Sized + Send> Send for AtomicRefCell<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for AtomicRefCell<T> {}
+
+impl<T> AtomicRefCell<T> {
+    pub fn new(value: T) -> AtomicRefCell<T> {
+        AtomicRefCell {
+            borrows: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> AtomicRefCell<T> {
+    /// Borrow the value, None if it is already mutably borrowed
+    pub fn try_borrow(&self) -> Option<Ref<'_, T>> {
+        let mut borrows = self.borrows.load(Ordering::Relaxed);
+        loop {
+            if borrows >= WRITING - 1 {
+                return None;
+            }
+            match self.borrows.compare_exchange_weak(
+                borrows,
+                borrows + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => borrows = current,
+            }
+        }
+        Some(Ref {
+            value: unsafe { NonNull::new_unchecked(self.value.get()) },
+            borrows: &self.borrows,
+            record: None,
+            marker: PhantomData,
+        })
+    }
+
+    /// Mutably borrow the value, None if it is already borrowed
+    pub fn try_borrow_mut(&self) -> Option<RefMut<'_, T>> {
+        self.borrows
+            .compare_exchange(0, WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        Some(RefMut {
+            value: unsafe { NonNull::new_unchecked(self.value.get()) },
+            borrows: &self.borrows,
+            record: None,
+            marker: PhantomData,
+        })
+    }
+
+    /// Borrow the value, keeping location in locations until the borrow ends
+    /// None if it is already mutably borrowed
+    pub fn try_borrow_at<'b>(
+        &'b self,
+        locations: &'b BorrowLocations,
+        location: &'static Location<'static>,
+    ) -> Option<Ref<'b, T>> {
+        let mut borrow = self.try_borrow()?;
+        borrow.record = Some(locations.insert(location));
+        Some(borrow)
+    }
+
+    /// Mutably borrow the value, keeping location in locations until the borrow ends
+    /// None if it is already borrowed
+    pub fn try_borrow_mut_at<'b>(
+        &'b self,
+        locations: &'b BorrowLocations,
+        location: &'static Location<'static>,
+    ) -> Option<RefMut<'b, T>> {
+        let mut borrow = self.try_borrow_mut()?;
+        borrow.record = Some(locations.insert(location));
+        Some(borrow)
+    }
+
+    /// Borrow the value
+    /// Will panic if it is already mutably borrowed
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.try_borrow().expect("already mutably borrowed")
+    }
+
+    /// Mutably borrow the value
+    /// Will panic if it is already borrowed
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.try_borrow_mut().expect("already borrowed")
+    }
+
+    /// Get the value without any borrow tracking, as self is borrowed mutably
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for AtomicRefCell<T> {
+    fn default() -> AtomicRefCell<T> {
+        AtomicRefCell::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_borrow() {
+            Some(value) => f.debug_tuple("AtomicRefCell").field(&&*value).finish(),
+            None => f.write_str("AtomicRefCell(<borrowed>)"),
+        }
+    }
+}
+
+/// Where the live borrows of a cell were made, to explain borrow conflicts
+#[derive(Default)]
+pub struct BorrowLocations {
+    next_id: AtomicUsize,
+    live: Mutex<Vec<(usize, &'static Location<'static>)>>,
+}
+
+impl BorrowLocations {
+    /// Locations of the borrows that haven't ended yet, oldest first
+    pub fn live(&self) -> Vec<&'static Location<'static>> {
+        let live = self.live.lock().unwrap();
+        live.iter().map(|&(_, location)| location).collect()
+    }
+
+    fn insert(&self, location: &'static Location<'static>) -> Record<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.live.lock().unwrap().push((id, location));
+        Record {
+            locations: self,
+            id,
+        }
+    }
+}
+
+/// Entry of a live borrow in BorrowLocations, removed when the borrow ends
+struct Record<'b> {
+    locations: &'b BorrowLocations,
+    id: usize,
+}
+
+impl Drop for Record<'_> {
+    fn drop(&mut self) {
+        let mut live = self.locations.live.lock().unwrap();
+        live.retain(|&(id, _)| id != self.id);
+    }
+}
+
+/// A shared borrow of an AtomicRefCell
+pub struct Ref<'b, T: ?Sized> {
+    value: NonNull<T>,
+    borrows: &'b AtomicUsize,
+    record: Option<Record<'b>>,
+    marker: PhantomData<&'b T>,
+}
+
+// A Ref only gives shared access, like a &T
+unsafe impl<T: ?Sized + Sync> Send for Ref<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for Ref<'_, T> {}
+
+impl<'b, T: ?Sized> Ref<'b, T> {
+    /// Make a Ref to a part of the borrowed value
+    pub fn map<U: ?Sized, F: FnOnce(&T) -> &U>(mut orig: Ref<'b, T>, f: F) -> Ref<'b, U> {
+        let value = NonNull::from(f(unsafe { orig.value.as_ref() }));
+        let borrows = orig.borrows;
+        let record = orig.record.take();
+        // The borrow is handed over to the new Ref
+        std::mem::forget(orig);
+        Ref {
+            value,
+            borrows,
+            record,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'b, T: ?Sized> Deref for Ref<'b, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<'b, T: ?Sized> Drop for Ref<'b, T> {
+    fn drop(&mut self) {
+        // Forgotten before the borrow ends, so that conflicts never list an ended borrow
+        self.record.take();
+        self.borrows.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// A mutable borrow of an AtomicRefCell
+pub struct RefMut<'b, T: ?Sized> {
+    value: NonNull<T>,
+    borrows: &'b AtomicUsize,
+    record: Option<Record<'b>>,
+    marker: PhantomData<&'b mut T>,
+}
+
+// A RefMut gives exclusive access, like a &mut T
+unsafe impl<T: ?Sized + Send> Send for RefMut<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RefMut<'_, T> {}
+
+impl<'b, T: ?Sized> RefMut<'b, T> {
+    /// Make a RefMut to a part of the borrowed value
+    pub fn map<U: ?Sized, F: FnOnce(&mut T) -> &mut U>(
+        mut orig: RefMut<'b, T>,
+        f: F,
+    ) -> RefMut<'b, U> {
+        let value = NonNull::from(f(unsafe { orig.value.as_mut() }));
+        let borrows = orig.borrows;
+        let record = orig.record.take();
+        // The borrow is handed over to the new RefMut
+        std::mem::forget(orig);
+        RefMut {
+            value,
+            borrows,
+            record,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'b, T: ?Sized> Deref for RefMut<'b, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<'b, T: ?Sized> DerefMut for RefMut<'b, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.value.as_mut() }
+    }
+}
+
+impl<'b, T: ?Sized> Drop for RefMut<'b, T> {
+    fn drop(&mut self) {
+        self.record.take();
+        self.borrows.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_borrows() {
+        let cell = AtomicRefCell::new(3);
+
+        let borrow1 = cell.borrow();
+        let borrow2 = cell.borrow();
+
+        assert!(*borrow1 + *borrow2 == 6);
+        assert!(cell.try_borrow_mut().is_none());
+        drop((borrow1, borrow2));
+        assert!(cell.try_borrow_mut().is_some());
+    }
+
+    #[test]
+    fn mutable_borrow() {
+        let cell = AtomicRefCell::new(vec![1, 2]);
+
+        let mut borrow = RefMut::map(cell.borrow_mut(), |vec| &mut vec[1]);
+        *borrow += 1;
+
+        assert!(cell.try_borrow().is_none());
+        drop(borrow);
+        assert!(*cell.borrow() == [1, 3]);
+    }
+
+    #[test]
+    fn borrow_locations() {
+        let cell = AtomicRefCell::new(3);
+        let locations = BorrowLocations::default();
+        let here = Location::caller();
+
+        let borrow1 = cell.try_borrow_at(&locations, here).unwrap();
+        let borrow2 = Ref::map(cell.try_borrow_at(&locations, here).unwrap(), |value| value);
+        assert!(locations.live().len() == 2);
+        drop(borrow1);
+        assert!(locations.live() == [here]);
+        drop(borrow2);
+        assert!(locations.live().is_empty());
+
+        let borrow = cell.try_borrow_mut_at(&locations, here).unwrap();
+        assert!(cell.try_borrow_at(&locations, here).is_none());
+        assert!(locations.live() == [here]);
+        drop(borrow);
+        assert!(locations.live().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn conflicting_borrow() {
+        let cell = AtomicRefCell::new(3);
+
+        let _borrow = Ref::map(cell.borrow(), |value| value);
+        cell.borrow_mut();
+    }
+}
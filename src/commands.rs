@@ -0,0 +1,186 @@
+use crate::{Component, Engine, EntityIndex, ThreadSafe};
+use std::sync::Mutex;
+
+/// A structural change recorded to be applied later
+#[cfg(feature = "parallel")]
+type Command = Box<dyn FnOnce(&Engine) + Send>;
+
+/// A structural change recorded to be applied later
+#[cfg(not(feature = "parallel"))]
+type Command = Box<dyn FnOnce(&Engine)>;
+
+/// Queue of structural changes waiting for the next sync point
+#[derive(Default)]
+pub(crate) struct CommandQueue {
+    commands: Mutex<Vec<Command>>,
+}
+
+impl CommandQueue {
+    fn push(&self, command: Command) {
+        self.commands.lock().unwrap().push(command);
+    }
+
+    /// Take every recorded command, leaving the queue empty
+    pub(crate) fn take(&self) -> Vec<Command> {
+        std::mem::take(&mut *self.commands.lock().unwrap())
+    }
+}
+
+/// Records spawns, despawns and component changes to apply them at the next sync point
+/// The Engine applies them after every system, so they are safe to issue
+/// while iterating over a Query or holding a Storage
+pub struct Commands<'e> {
+    engine: &'e Engine,
+}
+
+impl<'e> Commands<'e> {
+    pub(crate) fn new(engine: &'e Engine) -> Commands<'e> {
+        Commands { engine }
+    }
+
+    /// Reserve a new entity, it will be alive once commands are applied
+    /// The returned index can be used right away to record other commands
+    pub fn spawn(&self) -> EntityIndex {
+        let index = self.engine.reserve_entity();
+        self.add(move |engine| engine.spawn_reserved(index));
+        index
+    }
+
+    /// Despawn an entity and all of its components
+    pub fn despawn(&self, index: EntityIndex) {
+        self.add(move |engine| {
+            engine.despawn_entity(index);
+        });
+    }
+
+    /// Despawn an entity along with all of its descendants
+    pub fn despawn_recursive(&self, index: EntityIndex) {
+        self.add(move |engine| {
+            engine.despawn_recursive(index);
+        });
+    }
+
+    /// Attach an entity to a parent, see Engine::set_parent
+    /// Ignored if either entity has been despawned by the time commands are applied,
+    /// or if parent has become a descendant of child
+    pub fn set_parent(&self, child: EntityIndex, parent: EntityIndex) {
+        self.add(move |engine| {
+            engine.try_set_parent(child, parent).ok();
+        });
+    }
+
+    /// Add a component to an entity
+    /// Ignored if the entity has been despawned by the time commands are applied
+    pub fn insert<T: 'static + Component>(&self, index: EntityIndex, component: T) {
+        self.add(move |engine| {
+            if engine.is_alive(index) {
+                engine.add_entity_component(index, component);
+            }
+        });
+    }
+
+    /// Remove a component from an entity
+    /// Ignored if the entity has been despawned by the time commands are applied
+    pub fn remove<T: 'static + Component>(&self, index: EntityIndex) {
+        self.add(move |engine| {
+            if engine.is_alive(index) {
+                engine.remove_entity_component::<T>(index);
+            }
+        });
+    }
+
+    /// Record any other operation on the Engine
+    pub fn add<F: 'static + FnOnce(&Engine) + ThreadSafe>(&self, command: F) {
+        self.engine.command_queue().push(Box::new(command));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::{new_engine, Position};
+
+    #[test]
+    fn spawn_is_deferred() {
+        let engine = new_engine();
+
+        let entity = engine.commands().spawn();
+        engine.commands().insert(entity, Position(3));
+
+        assert!(!engine.is_alive(entity));
+        engine.apply_commands();
+        assert!(engine.is_alive(entity));
+        assert!(engine.get_component_ref::<Position>().get(entity) == &Position(3));
+    }
+
+    #[test]
+    fn spawn_reserves_distinct_indices() {
+        let engine = new_engine();
+
+        let entity1 = engine.commands().spawn();
+        let entity2 = engine.create_entity();
+
+        assert!(entity1 != entity2);
+    }
+
+    #[test]
+    fn despawn_while_iterating() {
+        let engine = new_engine();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Position(3));
+
+        for (entity, _) in engine.query::<&mut Position>().iter_mut() {
+            engine.commands().despawn(entity);
+            let spawned = engine.commands().spawn();
+            engine.commands().insert(spawned, Position(4));
+        }
+        engine.apply_commands();
+
+        assert!(!engine.is_alive(entity));
+        let query = engine.query::<&Position>();
+        assert!(
+            query
+                .iter()
+                .map(|(_, position)| position.0)
+                .collect::<Vec<_>>()
+                == [4]
+        );
+    }
+
+    #[test]
+    fn insert_on_despawned_entity() {
+        let engine = new_engine();
+        let entity = engine.create_entity();
+
+        engine.commands().despawn(entity);
+        engine.commands().insert(entity, Position(3));
+        engine.apply_commands();
+
+        assert!(engine.query::<&Position>().is_empty());
+    }
+
+    #[test]
+    fn remove_component() {
+        let engine = new_engine();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Position(3));
+
+        engine.commands().remove::<Position>(entity);
+        engine.apply_commands();
+
+        assert!(engine.query::<&Position>().is_empty());
+    }
+
+    #[test]
+    fn set_parent_cycle() {
+        let engine = new_engine();
+        let entity1 = engine.create_entity();
+        let entity2 = engine.create_entity();
+
+        engine.commands().set_parent(entity1, entity2);
+        engine.commands().set_parent(entity2, entity1);
+        engine.apply_commands();
+
+        assert!(engine.parent(entity1) == Some(entity2));
+        assert!(engine.parent(entity2).is_none());
+    }
+}
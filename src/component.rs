@@ -1,3 +1,4 @@
+use crate::ThreadSafe;
 use std::any::Any;
 
-pub trait Component: Sized + Any {}
+pub trait Component: Sized + Any + ThreadSafe {}
@@ -1,6 +1,42 @@
+use crate::ComponentMask;
+use std::fmt;
+
 /// An index pointing to a unique entity
-pub type EntityIndex = u64;
-pub type ComponentMask = u64;
+/// The generation is bumped every time an index is recycled, so a handle to a
+/// despawned entity never aliases the entity now living in its slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityIndex {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityIndex {
+    /// Create a handle from its raw parts
+    pub fn new(index: u32, generation: u32) -> EntityIndex {
+        EntityIndex { index, generation }
+    }
+
+    /// Get the slot of the entity
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Get how many times the slot has been recycled
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Get the handle the next entity reusing this slot will have
+    pub(crate) fn next_generation(&self) -> EntityIndex {
+        EntityIndex::new(self.index, self.generation.wrapping_add(1))
+    }
+}
+
+impl fmt::Display for EntityIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
 
 /// A unique entity containing the components it's attached to
 pub struct Entity {
@@ -10,20 +46,22 @@ pub struct Entity {
 impl Entity {
     /// Create a new Entity wit
     pub fn new() -> Entity {
-        Entity { components_mask: 0 }
+        Entity {
+            components_mask: ComponentMask::new(),
+        }
     }
 
     /// Get the mask of the entity
-    pub fn components_mask(&self) -> ComponentMask {
-        self.components_mask
+    pub fn components_mask(&self) -> &ComponentMask {
+        &self.components_mask
     }
 
-    pub fn add_component(&mut self, component_mask: ComponentMask) {
+    pub fn add_component(&mut self, component_mask: &ComponentMask) {
         self.components_mask |= component_mask;
     }
 
-    pub fn remove_component(&mut self, component_mask: ComponentMask) {
-        self.components_mask &= !component_mask;
+    pub fn remove_component(&mut self, component_mask: &ComponentMask) {
+        self.components_mask.remove(component_mask);
     }
 }
 
@@ -35,29 +73,42 @@ mod test {
     fn new_entity() {
         let entity = Entity::new();
 
-        assert!(entity.components_mask() == 0);
+        assert!(entity.components_mask().is_empty());
     }
 
     #[test]
     fn add_components() {
         let mut entity = Entity::new();
 
-        entity.add_component(0b100000);
-        entity.add_component(0b1000);
+        entity.add_component(&ComponentMask::with_bit(5));
+        entity.add_component(&ComponentMask::with_bit(3));
 
-        assert!(entity.components_mask() == 0b101000);
+        assert!(
+            entity.components_mask() == &(ComponentMask::with_bit(5) | ComponentMask::with_bit(3))
+        );
     }
 
     #[test]
     fn remove_components() {
         let mut entity = Entity::new();
-        entity.add_component(0b100000);
-        entity.add_component(0b1000);
-        entity.add_component(0b100);
+        entity.add_component(&ComponentMask::with_bit(5));
+        entity.add_component(&ComponentMask::with_bit(3));
+        entity.add_component(&ComponentMask::with_bit(2));
+
+        entity.remove_component(&ComponentMask::with_bit(2));
+        entity.remove_component(&ComponentMask::with_bit(5));
+
+        assert!(entity.components_mask() == &ComponentMask::with_bit(3));
+    }
+
+    #[test]
+    fn next_generation() {
+        let entity = EntityIndex::new(4, 2);
 
-        entity.remove_component(0b100);
-        entity.remove_component(0b100000);
+        let recycled = entity.next_generation();
 
-        assert!(entity.components_mask() == 0b1000);
+        assert!(recycled.index() == 4);
+        assert!(recycled.generation() == 3);
+        assert!(recycled != entity);
     }
 }
@@ -0,0 +1,161 @@
+use crate::{EntityIndex, Stage};
+use std::error::Error;
+use std::fmt;
+use std::panic::Location;
+
+/// Everything that can go wrong in the Engine
+#[derive(Debug)]
+pub enum GerustError {
+    /// A component was used before being registered with Engine::register_component
+    ComponentNotRegistered { type_name: &'static str },
+
+    /// An entity index doesn't point to a living entity
+    NoSuchEntity(EntityIndex),
+
+    /// An entity can't be attached to one of its descendants
+    HierarchyCycle {
+        child: EntityIndex,
+        parent: EntityIndex,
+    },
+
+    /// An entity doesn't have the requested component
+    MissingComponent {
+        entity: EntityIndex,
+        type_name: &'static str,
+    },
+
+    /// A component storage is borrowed in a way that conflicts with the requested access
+    BorrowConflict {
+        type_name: &'static str,
+        /// Where the live borrows of the storage were made, oldest first
+        borrowed_at: Vec<&'static Location<'static>>,
+    },
+
+    /// SDL failed to initialize or render
+    Sdl(String),
+
+    /// A system returned an error
+    SystemFailed {
+        system: String,
+        source: Box<GerustError>,
+    },
+
+    /// A system is ordered relative to a name no system of its stage has
+    UnknownSystem {
+        system: String,
+        dependency: String,
+        stage: Stage,
+    },
+
+    /// Ordering constraints of a stage form a cycle, listed from its first system back to it
+    SystemOrderCycle { stage: Stage, cycle: Vec<String> },
+
+    /// A LoopConfig field is out of range, named along with the reason
+    InvalidLoopConfig {
+        field: &'static str,
+        reason: &'static str,
+    },
+
+    /// Any other error, typically raised by a game's own systems
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for GerustError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GerustError::ComponentNotRegistered { type_name } => {
+                write!(f, "Component {} has not been registered", type_name)
+            }
+            GerustError::NoSuchEntity(entity) => write!(f, "Entity {} does not exist", entity),
+            GerustError::HierarchyCycle { child, parent } => write!(
+                f,
+                "Entity {} can't be the parent of its ancestor {}",
+                parent, child
+            ),
+            GerustError::MissingComponent { entity, type_name } => {
+                write!(f, "Entity {} has no component {}", entity, type_name)
+            }
+            GerustError::BorrowConflict {
+                type_name,
+                borrowed_at,
+            } => {
+                write!(f, "Component {} is already borrowed", type_name)?;
+                for (i, location) in borrowed_at.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { " at" } else { "," }, location)?;
+                }
+                Ok(())
+            }
+            GerustError::Sdl(err) => write!(f, "SDL error: {}", err),
+            GerustError::SystemFailed { system, source } => {
+                write!(f, "System {} failed: {}", system, source)
+            }
+            GerustError::UnknownSystem {
+                system,
+                dependency,
+                stage,
+            } => write!(
+                f,
+                "System {} is ordered relative to {}, which is not in stage {}",
+                system, dependency, stage
+            ),
+            GerustError::SystemOrderCycle { stage, cycle } => write!(
+                f,
+                "Systems of stage {} have cyclic ordering constraints: {}",
+                stage,
+                cycle.join(" -> ")
+            ),
+            GerustError::InvalidLoopConfig { field, reason } => {
+                write!(f, "Invalid loop config: {} {}", field, reason)
+            }
+            GerustError::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for GerustError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GerustError::SystemFailed { source, .. } => Some(source.as_ref()),
+            GerustError::Other(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for GerustError {
+    fn from(err: String) -> Self {
+        GerustError::Other(err.into())
+    }
+}
+
+impl From<&str> for GerustError {
+    fn from(err: &str) -> Self {
+        GerustError::Other(err.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let err = GerustError::MissingComponent {
+            entity: EntityIndex::new(3, 1),
+            type_name: "Position",
+        };
+
+        assert!(err.to_string() == "Entity 3v1 has no component Position");
+    }
+
+    #[test]
+    fn system_failed_source() {
+        let err = GerustError::SystemFailed {
+            system: "Gravity".to_string(),
+            source: Box::new(GerustError::from("out of bounds")),
+        };
+
+        assert!(err.to_string() == "System Gravity failed: out of bounds");
+        assert!(err.source().unwrap().to_string() == "out of bounds");
+    }
+}
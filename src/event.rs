@@ -0,0 +1,218 @@
+use crate::cell::{Ref, RefMut};
+use crate::{Access, Engine, SystemParam, SystemTicks, ThreadSafe, Tick};
+use std::mem;
+
+/// Queue of events of type E sent between systems, registered with Engine::add_event
+/// Events are kept for two updates of the queue, so every system gets to read them once
+/// whether it runs before or after the system that sent them
+/// Engine::run updates it once per frame running fixed updates, Engine::step at every step
+pub struct Events<E> {
+    /// Events sent before the last update, along with the tick they were sent at
+    previous: Vec<(Tick, E)>,
+
+    /// Events sent since the last update
+    current: Vec<(Tick, E)>,
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Events<E> {
+        Events {
+            previous: vec![],
+            current: vec![],
+        }
+    }
+}
+
+impl<E> Events<E> {
+    /// Send an event at tick
+    pub fn send(&mut self, event: E, tick: Tick) {
+        self.current.push((tick, event));
+    }
+
+    /// Iterate over the events sent after ticks.last_run, oldest first
+    pub fn iter_since(&self, ticks: SystemTicks) -> impl Iterator<Item = &E> {
+        self.iter_with_ticks()
+            .filter(move |(tick, _)| ticks.is_newer(*tick))
+            .map(|(_, event)| event)
+    }
+
+    /// Iterate over every kept event, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &E> {
+        self.iter_with_ticks().map(|(_, event)| event)
+    }
+
+    fn iter_with_ticks(&self) -> impl Iterator<Item = &(Tick, E)> {
+        self.previous.iter().chain(self.current.iter())
+    }
+
+    /// Get the number of kept events
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    /// Check if no event is kept
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop the events sent before the previous update
+    /// The Engine calls it before the fixed updates of every frame, or before every step
+    pub fn update(&mut self) {
+        self.previous = mem::take(&mut self.current);
+    }
+
+    /// Drop every event
+    pub fn clear(&mut self) {
+        self.previous.clear();
+        self.current.clear();
+    }
+}
+
+/// Reads the events of type E sent since the system last ran
+/// The cursor of each reader is the tick of the previous run of its system
+pub struct EventReader<'e, E: 'static> {
+    events: Ref<'e, Events<E>>,
+    ticks: SystemTicks,
+}
+
+impl<'e, E: 'static> EventReader<'e, E> {
+    /// Iterate over the unread events, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &E> {
+        self.events.iter_since(self.ticks)
+    }
+
+    /// Get the number of unread events
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Check if there is no unread event
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+/// Sends events of type E to the readers
+pub struct EventWriter<'e, E: 'static> {
+    events: RefMut<'e, Events<E>>,
+    this_run: Tick,
+}
+
+impl<'e, E: 'static> EventWriter<'e, E> {
+    /// Send an event, readable until the second next update of the queue
+    pub fn send(&mut self, event: E) {
+        self.events.send(event, self.this_run);
+    }
+
+    /// Send every event of an iterator
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = E>) {
+        for event in events {
+            self.send(event);
+        }
+    }
+}
+
+impl<'w, E: 'static + ThreadSafe> SystemParam for EventReader<'w, E> {
+    type Item<'e> = EventReader<'e, E>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_read::<Events<E>>();
+    }
+
+    fn fetch(engine: &Engine, ticks: SystemTicks) -> Self::Item<'_> {
+        EventReader {
+            events: engine.resource(),
+            ticks,
+        }
+    }
+}
+
+impl<'w, E: 'static + ThreadSafe> SystemParam for EventWriter<'w, E> {
+    type Item<'e> = EventWriter<'e, E>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_write::<Events<E>>();
+    }
+
+    fn fetch(engine: &Engine, ticks: SystemTicks) -> Self::Item<'_> {
+        EventWriter {
+            events: engine.resource_mut(),
+            this_run: ticks.this_run,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{IntoSystemDescriptor, Res, ResMut, Stage, Time};
+    use std::time::Duration;
+
+    #[derive(Debug, PartialEq)]
+    struct Hit(u32);
+
+    #[test]
+    fn double_buffering() {
+        let mut events = Events::default();
+
+        events.send(Hit(1), 1);
+        events.update();
+        events.send(Hit(2), 2);
+
+        assert!(events.iter().collect::<Vec<_>>() == [&Hit(1), &Hit(2)]);
+        let ticks = SystemTicks {
+            last_run: 1,
+            this_run: 3,
+        };
+        assert!(events.iter_since(ticks).collect::<Vec<_>>() == [&Hit(2)]);
+        events.update();
+        assert!(events.iter().collect::<Vec<_>>() == [&Hit(2)]);
+        events.update();
+        assert!(events.is_empty());
+    }
+
+    #[derive(Default)]
+    struct Received(Vec<u32>);
+
+    #[test]
+    fn readers_see_events_once() {
+        let mut engine = Engine::headless();
+        engine.add_event::<Hit>();
+        engine.insert_resource(Received::default());
+        // The reader runs before the writer, so it reads events on the next update
+        engine.register_system(|hits: EventReader<Hit>, mut received: ResMut<Received>| {
+            received.0.extend(hits.iter().map(|hit| hit.0));
+        });
+        engine.register_system(|mut hits: EventWriter<Hit>, time: Res<Time>| {
+            hits.send(Hit(time.tick_count() as u32));
+        });
+        engine.send_event(Hit(0));
+
+        engine.step_n(3).unwrap();
+
+        assert!(engine.resource::<Received>().0 == [0, 1, 2]);
+    }
+
+    #[test]
+    fn render_reads_every_catch_up_update() {
+        let mut engine = Engine::headless();
+        engine.add_event::<Hit>();
+        engine.insert_resource(Received::default());
+        engine.register_system(
+            (|hits: EventReader<Hit>, mut received: ResMut<Received>| {
+                received.0.extend(hits.iter().map(|hit| hit.0));
+            })
+            .in_stage(Stage::Render),
+        );
+        engine.register_system(|mut hits: EventWriter<Hit>, time: Res<Time>| {
+            hits.send(Hit(time.tick_count() as u32));
+        });
+
+        for _ in 0..2 {
+            engine.frame_updates(3, &[]).unwrap();
+            engine.render(Duration::ZERO, 0., &[]).unwrap();
+        }
+
+        assert!(engine.resource::<Received>().0 == [1, 2, 3, 4, 5, 6]);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{Component, EntityIndex, Query, With, Without};
+use std::ops::Deref;
+
+/// Parent of an entity, maintained by Engine::set_parent
+/// Read it freely, but change it with Engine::set_parent and Engine::remove_parent
+/// so the Children of both entities stay in sync
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parent(pub(crate) EntityIndex);
+
+impl Parent {
+    /// Get the parent entity
+    pub fn get(&self) -> EntityIndex {
+        self.0
+    }
+}
+
+impl Component for Parent {}
+
+/// Children of an entity, in the order they were attached, maintained by Engine::set_parent
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Children(pub(crate) Vec<EntityIndex>);
+
+impl Deref for Children {
+    type Target = [EntityIndex];
+
+    fn deref(&self) -> &[EntityIndex] {
+        &self.0
+    }
+}
+
+impl Component for Children {}
+
+/// A transform relative to the parent of its entity, composed into a global one
+/// by the system added with Engine::add_transform_propagation
+pub trait Transform: Component {
+    /// Transform relative to the world
+    type Global: Component + PartialEq;
+
+    /// Global transform of an entity without parent
+    fn to_global(&self) -> Self::Global;
+
+    /// Global transform of an entity whose parent has the given global transform
+    fn compose(&self, parent: &Self::Global) -> Self::Global;
+}
+
+/// Set the global transform of every entity from its local transform and the ones of its ancestors
+/// Descendants of an entity without local transform are left untouched
+pub fn propagate_transforms<T: Transform>(
+    roots: Query<(&T, Option<&Children>), Without<Parent>>,
+    descendants: Query<(&T, Option<&Children>), With<Parent>>,
+    mut globals: Query<&mut T::Global>,
+) {
+    for (entity, (transform, children)) in roots.iter() {
+        let global = transform.to_global();
+        if let Some(children) = children {
+            propagate_children(children, &global, &descendants, &mut globals);
+        }
+        set_global(&mut globals, entity, global);
+    }
+}
+
+fn propagate_children<T: Transform>(
+    children: &[EntityIndex],
+    parent: &T::Global,
+    descendants: &Query<(&T, Option<&Children>), With<Parent>>,
+    globals: &mut Query<&mut T::Global>,
+) {
+    for &child in children {
+        let (transform, grandchildren) = match descendants.get(child) {
+            Some(item) => item,
+            None => continue,
+        };
+        let global = transform.compose(parent);
+        if let Some(grandchildren) = grandchildren {
+            propagate_children(grandchildren, &global, descendants, globals);
+        }
+        set_global(globals, child, global);
+    }
+}
+
+/// Set the global transform of an entity if it has one, only marking it as changed if it moved
+fn set_global<G: Component + PartialEq>(
+    globals: &mut Query<&mut G>,
+    entity: EntityIndex,
+    global: G,
+) {
+    if let Some(mut entity_global) = globals.get_mut(entity) {
+        if *entity_global != global {
+            *entity_global = global;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Engine, GerustError};
+
+    #[derive(Debug, PartialEq)]
+    struct GlobalOffset(i32);
+    impl Component for GlobalOffset {}
+
+    #[derive(Debug, PartialEq)]
+    struct Offset(i32);
+    impl Component for Offset {}
+    impl Transform for Offset {
+        type Global = GlobalOffset;
+
+        fn to_global(&self) -> GlobalOffset {
+            GlobalOffset(self.0)
+        }
+
+        fn compose(&self, parent: &GlobalOffset) -> GlobalOffset {
+            GlobalOffset(parent.0 + self.0)
+        }
+    }
+
+    #[test]
+    fn set_parent() {
+        let engine = Engine::headless();
+        let parent = engine.create_entity();
+        let child1 = engine.create_entity();
+        let child2 = engine.create_entity();
+
+        engine.set_parent(child1, parent);
+        engine.set_parent(child2, parent);
+
+        assert!(engine.parent(child1) == Some(parent));
+        assert!(engine.children(parent) == [child1, child2]);
+        assert!(engine.is_ancestor(parent, child2));
+    }
+
+    #[test]
+    fn reparent() {
+        let engine = Engine::headless();
+        let parent1 = engine.create_entity();
+        let parent2 = engine.create_entity();
+        let child = engine.create_entity();
+        engine.set_parent(child, parent1);
+
+        engine.set_parent(child, parent2);
+
+        assert!(engine.children(parent1).is_empty());
+        assert!(!engine.query::<&Children>().contains(parent1));
+        assert!(engine.children(parent2) == [child]);
+        assert!(engine.remove_parent(child) == Some(parent2));
+        assert!(engine.parent(child).is_none());
+    }
+
+    #[test]
+    fn parent_cycle() {
+        let engine = Engine::headless();
+        let grandparent = engine.create_entity();
+        let parent = engine.create_entity();
+        let child = engine.create_entity();
+        engine.set_parent(parent, grandparent);
+        engine.set_parent(child, parent);
+
+        let result = engine.try_set_parent(grandparent, child);
+
+        assert!(matches!(result, Err(GerustError::HierarchyCycle { .. })));
+        assert!(engine.parent(grandparent).is_none());
+    }
+
+    #[test]
+    fn despawn_orphans_children() {
+        let engine = Engine::headless();
+        let grandparent = engine.create_entity();
+        let parent = engine.create_entity();
+        let child = engine.create_entity();
+        engine.set_parent(parent, grandparent);
+        engine.set_parent(child, parent);
+
+        engine.despawn_entity(parent);
+
+        assert!(engine.children(grandparent).is_empty());
+        assert!(engine.is_alive(child));
+        assert!(engine.parent(child).is_none());
+    }
+
+    #[test]
+    fn despawn_recursive() {
+        let engine = Engine::headless();
+        let root = engine.create_entity();
+        let parent = engine.create_entity();
+        let child = engine.create_entity();
+        let other = engine.create_entity();
+        engine.set_parent(parent, root);
+        engine.set_parent(child, parent);
+        engine.set_parent(other, root);
+
+        engine.commands().despawn_recursive(parent);
+        engine.apply_commands();
+
+        assert!(!engine.is_alive(parent) && !engine.is_alive(child));
+        assert!(engine.children(root) == [other]);
+    }
+
+    #[test]
+    fn propagate() {
+        let mut engine = Engine::headless();
+        engine.register_component::<Offset>();
+        engine.register_component::<GlobalOffset>();
+        engine.add_transform_propagation::<Offset>();
+        let entities: Vec<EntityIndex> = (0..3).map(|_| engine.create_entity()).collect();
+        for (i, &entity) in entities.iter().enumerate() {
+            engine.add_entity_component(entity, Offset(10_i32.pow(i as u32)));
+            engine.add_entity_component(entity, GlobalOffset(0));
+        }
+        engine.set_parent(entities[2], entities[1]);
+        engine.set_parent(entities[1], entities[0]);
+
+        engine.step_n(1).unwrap();
+
+        let globals = engine.query::<&GlobalOffset>();
+        assert!(globals.get(entities[0]) == Some(&GlobalOffset(1)));
+        assert!(globals.get(entities[1]) == Some(&GlobalOffset(11)));
+        assert!(globals.get(entities[2]) == Some(&GlobalOffset(111)));
+    }
+}
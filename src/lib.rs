@@ -1,25 +1,87 @@
+mod access;
+mod archetype;
+mod camera;
+mod cell;
+mod commands;
 mod component;
 mod entity;
+mod error;
+mod event;
+mod hierarchy;
+mod mask;
+mod math;
+mod query;
+mod resource;
+mod schedule;
+mod sparse_set;
+mod sprite;
+mod stage;
 mod storage;
 mod system;
+mod system_param;
+#[cfg(test)]
+mod test_utils;
+#[cfg(feature = "ttf")]
+mod text;
+mod thread_safe;
+mod tick;
+mod time;
+mod transform;
 
+pub use access::Access;
+pub use archetype::{ArchetypeId, ArchetypeStorage, EMPTY_ARCHETYPE};
+pub use camera::Camera2D;
+pub use cell::{AtomicRefCell, Ref, RefMut};
+pub use commands::Commands;
 pub use component::Component;
-pub use entity::{ComponentMask, EntityIndex};
-pub use storage::{Storage, StorageTrait};
-pub use system::System;
+pub use entity::EntityIndex;
+pub use error::GerustError;
+pub use event::{EventReader, EventWriter, Events};
+pub use hierarchy::{propagate_transforms, Children, Parent, Transform};
+pub use mask::ComponentMask;
+pub use math::Vec2;
+pub use query::{
+    Added, Changed, Fetch, Mut, Query, QueryFilter, QueryIter, ReadOnlyFetch, With, Without,
+};
+pub use resource::{InputEvents, Resources};
+pub use sparse_set::SparseSet;
+pub use sprite::{render_sprites, ClearColor, Sprite, TextureHandle, Textures};
+pub use stage::Stage;
+pub use storage::{ComponentSet, Storage, StorageTrait};
+pub use system::{
+    FunctionSystem, IntoSystem, IntoSystemDescriptor, System, SystemDescriptor, SystemOutput,
+    SystemParamFunction,
+};
+pub use system_param::{NonSend, NonSendMut, RemovedComponents, Res, ResMut, SystemParam};
+#[cfg(feature = "ttf")]
+pub use text::{render_text, Alignment, FontHandle, Fonts, Text};
+pub use thread_safe::ThreadSafe;
+pub use tick::{ComponentTicks, SystemTicks, Tick, MAX_CHANGE_AGE};
+pub use time::{LoopConfig, Time};
+pub use transform::{
+    insert_global_transforms, integrate_velocity, GlobalTransform2D, Transform2D, Velocity2D,
+};
 
+use archetype::Archetypes;
+use commands::CommandQueue;
 use entity::Entity;
+use resource::NonSendResources;
+use schedule::Schedule;
 use sdl2::event::Event;
+use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 use sdl2::EventPump;
-use std::any::{Any, TypeId};
-use std::cell::{RefCell, RefMut};
+use std::any::{self, TypeId};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
 use std::thread;
 use std::time::{Duration, Instant};
-
-const FRAMERATE: f64 = 60.;
+use storage::{AnyStorage, StorageCell, TrackedStorage};
+use thread_safe::MainThread;
+use tick::CHECK_TICK_THRESHOLD;
 
 pub enum UpdateStatus {
     Continue,
@@ -29,69 +91,496 @@ pub enum UpdateStatus {
 /// Engine represents the global state of the game
 pub struct Engine {
     /// Current entites in our Engine
-    pub entities: RefCell<HashMap<EntityIndex, Entity>>,
+    pub entities: AtomicRefCell<HashMap<EntityIndex, Entity>>,
 
     /// Component storage
-    /// Note: There is an Any instead of a StorageTrait, as Rust doesn't support
+    /// Note: There is an AnyStorage instead of a StorageTrait, as Rust doesn't support
     /// dyn with generic traits.
     /// This means we need to downcast to our Storage impl
-    components: HashMap<TypeId, Box<dyn Any>>,
+    components: HashMap<TypeId, Box<dyn AnyStorage>>,
 
     /// The associated mask for each Component
     component_masks: HashMap<TypeId, ComponentMask>,
 
-    /// The registered systems
-    systems: Vec<Box<dyn System>>,
+    /// The Component of each mask bit, in registration order
+    component_types: Vec<TypeId>,
+
+    /// Components whose storage is organised by archetype
+    archetype_components: ComponentMask,
+
+    /// Entities grouped by component set
+    archetypes: AtomicRefCell<Archetypes>,
+
+    /// The registered systems of each stage, indexed by Stage::index
+    schedules: [Schedule; 4],
+
+    /// Global data not attached to any entity
+    resources: Resources,
+
+    /// Update of the Events resource of every event type added with add_event
+    event_updates: Vec<fn(&Engine)>,
+
+    /// Global data that can't leave the main thread, such as the Canvas
+    non_send: NonSendResources,
+
+    /// Configuration of the game loop
+    loop_config: LoopConfig,
+
+    /// The next never used entity slot
+    next_free: AtomicU32,
+
+    /// Handles of despawned entities, ready to be reused with a bumped generation
+    free_indices: Mutex<Vec<EntityIndex>>,
+
+    /// Tick given to the next function system run, and to changes made outside of systems
+    change_tick: AtomicU32,
+
+    /// Value of change_tick when the previous fixed update started
+    last_step_tick: Tick,
 
-    /// The next available entity index
-    next_free: RefCell<EntityIndex>,
+    /// Value of change_tick when old ticks were last clamped
+    last_check_tick: Tick,
 
-    /// Rendering is done on the canvas,
-    pub canvas: RefCell<Canvas<Window>>,
+    /// Structural changes recorded by systems, applied after each batch of them
+    commands: CommandQueue,
 
-    /// Event loop
-    events: EventPump,
+    /// Event loop, None for headless engines
+    events: MainThread<Option<EventPump>>,
 }
 
 impl Engine {
     /// Create a new Engine
-    pub fn new(title: &str, width: u32, height: u32) -> Result<Engine, String> {
-        let sdl_context = sdl2::init()?;
-        let video_subsystem = sdl_context.video()?;
+    /// Its window shows the sprites of the world through a Camera2D where world coordinates match pixels
+    pub fn new(title: &str, width: u32, height: u32) -> Result<Engine, GerustError> {
+        let sdl_context = sdl2::init().map_err(GerustError::Sdl)?;
+        let video_subsystem = sdl_context.video().map_err(GerustError::Sdl)?;
 
         let window = video_subsystem
             .window(title, width, height)
             .position_centered()
             .build()
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| GerustError::Sdl(e.to_string()))?;
 
         let mut canvas = window
             .into_canvas()
             .accelerated()
             .build()
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| GerustError::Sdl(e.to_string()))?;
 
         canvas.present();
 
-        Ok(Engine {
-            entities: RefCell::new(HashMap::new()),
+        let mut engine = Engine {
+            events: MainThread::new(Some(sdl_context.event_pump().map_err(GerustError::Sdl)?)),
+            ..Engine::headless()
+        };
+        engine.insert_resource(ClearColor::default());
+        engine.insert_non_send_resource(Textures::new(&canvas));
+        engine.insert_non_send_resource(canvas);
+        engine.register_render_system(render_sprites.named("render_sprites"));
+        #[cfg(feature = "ttf")]
+        {
+            engine.insert_non_send_resource(Fonts::default());
+            engine.register_render_system(render_text.named("render_text").after("render_sprites"));
+        }
+        let camera = engine.create_entity();
+        engine.add_entity_component(camera, Camera2D::new(Rect::new(0, 0, width, height)));
+        Ok(engine)
+    }
+
+    /// Create a new Engine without any window, canvas or event loop
+    /// Only the ECS is available, which is enough for simulations, servers and tests
+    /// Like any Engine, it moves entities with a Velocity2D and computes their GlobalTransform2D
+    pub fn headless() -> Engine {
+        let mut resources = Resources::new();
+        resources.insert(Time::default());
+        resources.insert(InputEvents::default());
+        let mut engine = Engine {
+            entities: AtomicRefCell::new(HashMap::new()),
             components: HashMap::new(),
             component_masks: HashMap::new(),
-            systems: vec![],
-            next_free: RefCell::new(0),
-            canvas: RefCell::new(canvas),
-            events: sdl_context.event_pump()?,
-        })
+            component_types: vec![],
+            archetype_components: ComponentMask::new(),
+            archetypes: AtomicRefCell::new(Archetypes::new()),
+            schedules: Stage::ALL.map(Schedule::new),
+            resources,
+            event_updates: vec![],
+            non_send: NonSendResources::new(),
+            loop_config: LoopConfig::default(),
+            next_free: AtomicU32::new(0),
+            free_indices: Mutex::new(vec![]),
+            change_tick: AtomicU32::new(1),
+            last_step_tick: 0,
+            last_check_tick: 0,
+            commands: CommandQueue::default(),
+            events: MainThread::new(None),
+        };
+        engine.register_component::<Parent>();
+        engine.register_component::<Children>();
+        engine.register_component::<Transform2D>();
+        engine.register_component::<GlobalTransform2D>();
+        engine.register_component::<Velocity2D>();
+        engine.register_component::<Sprite>();
+        engine.register_component::<Camera2D>();
+        #[cfg(feature = "ttf")]
+        engine.register_component::<Text>();
+        engine.register_system(integrate_velocity.named("integrate_velocity"));
+        engine.register_system(
+            insert_global_transforms
+                .named("insert_global_transforms")
+                .in_stage(Stage::PostUpdate)
+                .before("propagate_transforms"),
+        );
+        engine.add_transform_propagation::<Transform2D>();
+        engine
+    }
+
+    /// Check if the Engine was created without a window
+    pub fn is_headless(&self) -> bool {
+        self.events.get().is_none()
+    }
+
+    /// Insert a global resource, returning the previous one of the same type
+    /// The Engine provides Time and InputEvents
+    pub fn insert_resource<R: 'static + ThreadSafe>(&mut self, resource: R) -> Option<R> {
+        self.resources.insert(resource)
+    }
+
+    /// Remove a global resource, returning it if it existed
+    pub fn remove_resource<R: 'static>(&mut self) -> Option<R> {
+        self.resources.remove()
+    }
+
+    /// Check if a global resource exists
+    pub fn contains_resource<R: 'static>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+
+    /// Get a global resource
+    /// Will panic if it doesn't exist or is already mutably borrowed
+    pub fn resource<R: 'static>(&self) -> Ref<'_, R> {
+        self.resources.get()
+    }
+
+    /// Mutably get a global resource
+    /// Will panic if it doesn't exist or is already borrowed
+    pub fn resource_mut<R: 'static>(&self) -> RefMut<'_, R> {
+        self.resources.get_mut()
+    }
+
+    /// Get a global resource, None if it doesn't exist
+    pub fn try_resource<R: 'static>(&self) -> Option<Ref<'_, R>> {
+        self.resources.try_get()
+    }
+
+    /// Mutably get a global resource, None if it doesn't exist
+    pub fn try_resource_mut<R: 'static>(&self) -> Option<RefMut<'_, R>> {
+        self.resources.try_get_mut()
+    }
+
+    /// Add an event type, stored in an Events<E> resource updated before the fixed updates
+    /// of every frame, or before every step
+    /// Systems send and read events with EventWriter<E> and EventReader<E>
+    pub fn add_event<E: 'static + ThreadSafe>(&mut self) {
+        if self.contains_resource::<Events<E>>() {
+            return;
+        }
+        self.insert_resource(Events::<E>::default());
+        self.event_updates
+            .push(|engine| engine.resource_mut::<Events<E>>().update());
+    }
+
+    /// Send an event from outside of the systems
+    /// Will panic if the event type has not been added or is being read
+    pub fn send_event<E: 'static>(&self, event: E) {
+        self.resource_mut::<Events<E>>()
+            .send(event, self.change_tick());
+    }
+
+    /// Insert a resource that can't leave the main thread, returning the previous one
+    /// The Engine provides the Canvas<Window> unless headless
+    pub fn insert_non_send_resource<R: 'static>(&mut self, resource: R) -> Option<R> {
+        self.non_send.insert(resource)
+    }
+
+    /// Remove a resource that can't leave the main thread, returning it if it existed
+    pub fn remove_non_send_resource<R: 'static>(&mut self) -> Option<R> {
+        self.non_send.remove()
+    }
+
+    /// Check if a resource that can't leave the main thread exists
+    /// Will panic if called from another thread
+    pub fn contains_non_send_resource<R: 'static>(&self) -> bool {
+        self.non_send.contains::<R>()
+    }
+
+    /// Get a resource that can't leave the main thread
+    /// Will panic if it doesn't exist, is already mutably borrowed or if called from another thread
+    pub fn non_send_resource<R: 'static>(&self) -> Ref<'_, R> {
+        self.try_non_send_resource()
+            .unwrap_or_else(|| panic!("Could not get resource {}", any::type_name::<R>()))
+    }
+
+    /// Mutably get a resource that can't leave the main thread
+    /// Will panic if it doesn't exist, is already borrowed or if called from another thread
+    pub fn non_send_resource_mut<R: 'static>(&self) -> RefMut<'_, R> {
+        self.try_non_send_resource_mut()
+            .unwrap_or_else(|| panic!("Could not get resource {}", any::type_name::<R>()))
+    }
+
+    /// Get a resource that can't leave the main thread, None if it doesn't exist
+    /// Will panic if it is already mutably borrowed or if called from another thread
+    pub fn try_non_send_resource<R: 'static>(&self) -> Option<Ref<'_, R>> {
+        self.non_send.cell().map(AtomicRefCell::borrow)
+    }
+
+    /// Mutably get a resource that can't leave the main thread, None if it doesn't exist
+    /// Will panic if it is already borrowed or if called from another thread
+    pub fn try_non_send_resource_mut<R: 'static>(&self) -> Option<RefMut<'_, R>> {
+        self.non_send.cell().map(AtomicRefCell::borrow_mut)
+    }
+
+    /// Load a texture for Sprites, see Textures::load
+    /// Fails on headless engines, which have no canvas to draw textures on
+    pub fn load_texture(&self, path: impl AsRef<Path>) -> Result<TextureHandle, GerustError> {
+        match self.try_non_send_resource_mut::<Textures>() {
+            Some(mut textures) => textures.load(path),
+            None => Err(GerustError::Sdl(
+                "No canvas to load textures for".to_string(),
+            )),
+        }
     }
 
     /// Create a new entity and return its index
+    /// Slots of despawned entities are reused before new ones are allocated
     pub fn create_entity(&self) -> EntityIndex {
-        let index = *self.next_free.borrow();
-        self.entities.borrow_mut().insert(index, Entity::new());
-        *self.next_free.borrow_mut() += 1;
+        let index = self.reserve_entity();
+        self.spawn_reserved(index);
         index
     }
 
+    /// Pick the index of the next entity without spawning it
+    fn reserve_entity(&self) -> EntityIndex {
+        match self.free_indices.lock().unwrap().pop() {
+            Some(index) => index,
+            None => EntityIndex::new(self.next_free.fetch_add(1, Ordering::Relaxed), 0),
+        }
+    }
+
+    /// Spawn an entity at an index given by reserve_entity
+    fn spawn_reserved(&self, index: EntityIndex) {
+        self.entities.borrow_mut().insert(index, Entity::new());
+        self.archetypes.borrow_mut().insert(index);
+    }
+
+    /// Destroy an entity and all of its components
+    /// Its children lose their parent, use despawn_recursive to destroy them too
+    /// Returns false if the entity was already despawned
+    /// Will panic if a Storage of the entity, or one its parent and children move in, is borrowed
+    pub fn despawn_entity(&self, index: EntityIndex) -> bool {
+        self.try_despawn_entity(index)
+            .unwrap_or_else(|err| panic!("Could not despawn entity: {}", err))
+    }
+
+    /// Destroy an entity and all of its components
+    /// Its children lose their parent, use despawn_recursive to destroy them too
+    /// Returns false if the entity was already despawned
+    /// Fails, leaving the entity alive, if a Storage of the entity, or one its parent and
+    /// children move in, is borrowed
+    pub fn try_despawn_entity(&self, index: EntityIndex) -> Result<bool, GerustError> {
+        if !self.is_alive(index) {
+            return Ok(false);
+        }
+        self.check_despawn(index)?;
+        self.remove_parent(index);
+        for child in self.children(index) {
+            self.remove_parent(child);
+        }
+        let entity = match self.entities.borrow_mut().remove(&index) {
+            Some(entity) => entity,
+            None => return Ok(false),
+        };
+        self.archetypes.borrow_mut().remove(index);
+        for bit in entity.components_mask().bits() {
+            self.components[&self.component_types[bit]].remove_entity(index, self.change_tick());
+        }
+        self.free_indices
+            .lock()
+            .unwrap()
+            .push(index.next_generation());
+        Ok(true)
+    }
+
+    /// Check that every Storage despawning an entity changes can be mutably borrowed
+    /// Its parent and children only move between archetypes as they lose it
+    fn check_despawn(&self, index: EntityIndex) -> Result<(), GerustError> {
+        let mut related = vec![];
+        {
+            let parents = self.try_get_component_ref::<Parent>()?;
+            let children = self.try_get_component_ref::<Children>()?;
+            related.extend(parents.try_get(index).ok().map(Parent::get));
+            related.extend(
+                children
+                    .try_get(index)
+                    .map_or(vec![], |children| children.to_vec()),
+            );
+        }
+        let mut mask = ComponentMask::new();
+        if !related.is_empty() {
+            mask |= &self.try_get_mask::<Parent>()?;
+            mask |= &self.try_get_mask::<Children>()?;
+        }
+        {
+            let entities = self.entities.borrow();
+            for entity in related.iter().filter_map(|index| entities.get(index)) {
+                mask |= &(entity.components_mask() & &self.archetype_components);
+            }
+            mask |= entities[&index].components_mask();
+        }
+        for bit in mask.bits() {
+            self.components[&self.component_types[bit]].try_borrow_mover()?;
+        }
+        Ok(())
+    }
+
+    /// Destroy an entity along with all of its descendants
+    /// Returns false if the entity was already despawned
+    pub fn despawn_recursive(&self, index: EntityIndex) -> bool {
+        for child in self.children(index) {
+            self.despawn_recursive(child);
+        }
+        self.despawn_entity(index)
+    }
+
+    /// Attach an entity to a parent, detaching it from its previous one
+    /// Will panic if either entity doesn't exist or if parent is a descendant of child
+    pub fn set_parent(&self, child: EntityIndex, parent: EntityIndex) {
+        if let Err(err) = self.try_set_parent(child, parent) {
+            panic!("Could not set parent: {}", err);
+        }
+    }
+
+    /// Attach an entity to a parent, detaching it from its previous one
+    /// Fails if either entity doesn't exist or if parent is a descendant of child
+    pub fn try_set_parent(
+        &self,
+        child: EntityIndex,
+        parent: EntityIndex,
+    ) -> Result<(), GerustError> {
+        for index in [child, parent] {
+            if !self.is_alive(index) {
+                return Err(GerustError::NoSuchEntity(index));
+            }
+        }
+        if self.parent(child) == Some(parent) {
+            return Ok(());
+        }
+        if child == parent || self.is_ancestor(child, parent) {
+            return Err(GerustError::HierarchyCycle { child, parent });
+        }
+        self.remove_parent(child);
+        self.add_entity_component(child, Parent(parent));
+        let added = self
+            .query::<&mut Children>()
+            .get_mut(parent)
+            .map(|mut children| children.0.push(child))
+            .is_some();
+        if !added {
+            self.add_entity_component(parent, Children(vec![child]));
+        }
+        Ok(())
+    }
+
+    /// Detach an entity from its parent, returning the parent if it had one
+    pub fn remove_parent(&self, child: EntityIndex) -> Option<EntityIndex> {
+        let parent = self.parent(child)?;
+        self.remove_entity_component::<Parent>(child);
+        let now_empty = self
+            .query::<&mut Children>()
+            .get_mut(parent)
+            .map(|mut children| {
+                children.0.retain(|&index| index != child);
+                children.is_empty()
+            })
+            .unwrap_or(false);
+        if now_empty {
+            self.remove_entity_component::<Children>(parent);
+        }
+        Some(parent)
+    }
+
+    /// Get the parent of an entity
+    pub fn parent(&self, index: EntityIndex) -> Option<EntityIndex> {
+        self.query::<&Parent>().get(index).map(Parent::get)
+    }
+
+    /// Get the children of an entity, in the order they were attached
+    pub fn children(&self, index: EntityIndex) -> Vec<EntityIndex> {
+        self.query::<&Children>()
+            .get(index)
+            .map(|children| children.to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Check if ancestor is the parent of entity, or the parent of its parent, and so on
+    pub fn is_ancestor(&self, ancestor: EntityIndex, entity: EntityIndex) -> bool {
+        let mut current = self.parent(entity);
+        while let Some(parent) = current {
+            if parent == ancestor {
+                return true;
+            }
+            current = self.parent(parent);
+        }
+        false
+    }
+
+    /// Run propagate_transforms::<T> after every fixed update, in Stage::PostUpdate
+    /// Entities need both T and T::Global for their global transform to be set
+    pub fn add_transform_propagation<T: Transform>(&mut self) {
+        self.register_system(
+            propagate_transforms::<T>
+                .named("propagate_transforms")
+                .in_stage(Stage::PostUpdate),
+        );
+    }
+
+    /// Update the components mask of an entity and move it to the matching archetype
+    /// Storages are borrowed before anything changes, except the ones of handled, moved by the caller
+    fn change_components(
+        &self,
+        index: EntityIndex,
+        handled: &ComponentMask,
+        change: impl FnOnce(&mut Entity),
+    ) -> Result<ArchetypeId, GerustError> {
+        let mut entities = self.entities.borrow_mut();
+        let entity = entities
+            .get_mut(&index)
+            .ok_or(GerustError::NoSuchEntity(index))?;
+        let mut changed = Entity::new();
+        changed.add_component(entity.components_mask());
+        change(&mut changed);
+        let mut movers = vec![];
+        for bit in (changed.components_mask() & &self.archetype_components).bits() {
+            if !handled.contains(bit) {
+                movers.push(self.components[&self.component_types[bit]].try_borrow_mover()?);
+            }
+        }
+
+        let archetype = self
+            .archetypes
+            .borrow_mut()
+            .move_entity(index, changed.components_mask());
+        *entity = changed;
+        for mover in &mut movers {
+            mover.move_to_archetype(index, archetype);
+        }
+        Ok(archetype)
+    }
+
+    /// Check if a handle still points to a living entity
+    pub fn is_alive(&self, index: EntityIndex) -> bool {
+        self.entities.borrow().contains_key(&index)
+    }
+
     /// Add a component to an entity
     /// Will panic if given index doesn't exist or Component has not been registered
     pub fn add_entity_component<T: 'static + Component>(
@@ -99,82 +588,470 @@ impl Engine {
         entity_index: EntityIndex,
         component: T,
     ) {
-        self.entities
-            .borrow_mut()
-            .get_mut(&entity_index)
-            .unwrap()
-            .add_component(self.component_masks[&TypeId::of::<T>()]);
-        self.get_component::<T>()
-            .add_entity(entity_index, component);
+        if let Err(err) = self.try_add_entity_component(entity_index, component) {
+            panic!("Could not add component: {}", err);
+        }
     }
 
-    /// Remove a component from an entity
+    /// Add a component to an entity
+    /// Fails if given index doesn't exist or Component has not been registered
+    pub fn try_add_entity_component<T: 'static + Component>(
+        &self,
+        entity_index: EntityIndex,
+        component: T,
+    ) -> Result<(), GerustError> {
+        let mask = self.try_get_mask::<T>()?;
+        // Borrowed before the entity changes, so that a conflict leaves it untouched
+        let mut tracked = self.try_get_tracked_mut::<T>()?;
+        let archetype =
+            self.change_components(entity_index, &mask, |entity| entity.add_component(&mask))?;
+        tracked.storage.move_to_archetype(entity_index, archetype);
+        let ticks = match tracked.storage.ticks(entity_index) {
+            // Replacing a component changes it, it was still added by the first one
+            Some(mut ticks) => {
+                ticks.set_changed(self.change_tick());
+                ticks
+            }
+            None => ComponentTicks::new(self.change_tick()),
+        };
+        tracked
+            .storage
+            .add_entity_with_ticks(entity_index, component, ticks);
+        Ok(())
+    }
+
+    /// Remove a component from an entity, returning it if the entity had one
     /// Will panic if given index doesn't exist or Component has not been registered
-    pub fn remove_entity_component<T: 'static + Component>(&mut self, index: EntityIndex) {
-        self.entities
-            .borrow_mut()
-            .get_mut(&index)
-            .unwrap()
-            .remove_component(self.component_masks[&TypeId::of::<T>()]);
+    pub fn remove_entity_component<T: 'static + Component>(&self, index: EntityIndex) -> Option<T> {
+        self.try_remove_entity_component(index)
+            .unwrap_or_else(|err| panic!("Could not remove component: {}", err))
     }
 
-    /// Register a component for a Engine
+    /// Remove a component from an entity, returning it if the entity had one
+    /// Fails if given index doesn't exist or Component has not been registered
+    pub fn try_remove_entity_component<T: 'static + Component>(
+        &self,
+        index: EntityIndex,
+    ) -> Result<Option<T>, GerustError> {
+        let mask = self.try_get_mask::<T>()?;
+        // Borrowed before the entity changes, so that a conflict leaves it untouched
+        let mut tracked = self.try_get_tracked_mut::<T>()?;
+        self.change_components(index, &mask, |entity| entity.remove_component(&mask))?;
+        let removed = tracked.storage.remove_entity(index);
+        if removed.is_some() {
+            tracked.removals.push(index, self.change_tick());
+        }
+        Ok(removed)
+    }
+
+    /// Remove a component from every entity, returning them along with their entity
+    /// Will panic if Component has not been registered
+    pub fn drain_component<T: 'static + Component>(&self) -> Vec<(EntityIndex, T)> {
+        let mask = self.get_mask::<T>();
+        let mut tracked = self
+            .try_get_tracked_mut::<T>()
+            .unwrap_or_else(|err| panic!("Could not remove component: {}", err));
+        let indices: Vec<_> = tracked.storage.entities().collect();
+        let mut drained = Vec::with_capacity(indices.len());
+        for index in indices {
+            // Each entity changes before its component leaves, so a conflict keeps both in sync
+            self.change_components(index, &mask, |entity| entity.remove_component(&mask))
+                .unwrap_or_else(|err| panic!("Could not remove component: {}", err));
+            if let Some(component) = tracked.storage.remove_entity(index) {
+                tracked.removals.push(index, self.change_tick());
+                drained.push((index, component));
+            }
+        }
+        drained
+    }
+
+    /// Register a component for a Engine, stored in the default HashMap Storage
     pub fn register_component<T: 'static + Component>(&mut self) {
-        let storage: Storage<T> = Storage::new();
-        self.components
-            .insert(TypeId::of::<T>(), Box::new(RefCell::new(storage)));
+        self.register_component_with_storage::<T, Storage<T>>();
+    }
+
+    /// Register a component for a Engine, stored in the given StorageTrait implementation
+    /// e.g. `engine.register_component_with_storage::<Particle, SparseSet<Particle>>()`
+    pub fn register_component_with_storage<T, S>(&mut self)
+    where
+        T: 'static + Component,
+        S: 'static + StorageTrait<T>,
+    {
+        let storage: Box<dyn StorageTrait<T>> = Box::new(S::new());
+        let cell = StorageCell::new(storage);
+        self.components.insert(TypeId::of::<T>(), Box::new(cell));
 
         // Current ComponentMask creation relies on the fact that
         // a Component cannot be unregistered
-        let mask = 1u64 << self.component_masks.len();
-        self.component_masks.insert(TypeId::of::<T>(), mask);
+        let bit = self.component_types.len();
+        self.component_types.push(TypeId::of::<T>());
+        self.component_masks
+            .insert(TypeId::of::<T>(), ComponentMask::with_bit(bit));
+        if S::tracks_archetypes() {
+            self.archetype_components.insert(bit);
+        }
+    }
+
+    /// Retrieve the Storage of a component for reading
+    /// Will panic if component has not been registered or is already mutably borrowed
+    #[track_caller]
+    pub fn get_component_ref<T: 'static + Component>(&self) -> Ref<'_, dyn StorageTrait<T>> {
+        self.try_get_component_ref()
+            .unwrap_or_else(|err| panic!("Could not get component: {}", err))
+    }
+
+    /// Retrieve the Storage of a component for reading
+    /// Fails if component has not been registered or is already mutably borrowed
+    #[track_caller]
+    pub fn try_get_component_ref<T: 'static + Component>(
+        &self,
+    ) -> Result<Ref<'_, dyn StorageTrait<T>>, GerustError> {
+        self.try_storage_cell::<T>()?.try_borrow()
+    }
+
+    /// Retrieve the Storage of a component for writing
+    /// Changes made through the Storage aren't tracked, mutable Queries mark components as changed
+    /// Will panic if component has not been registered or is already borrowed
+    #[track_caller]
+    pub fn get_component_mut<T: 'static + Component>(&self) -> RefMut<'_, dyn StorageTrait<T>> {
+        self.try_get_component_mut()
+            .unwrap_or_else(|err| panic!("Could not get component: {}", err))
+    }
+
+    /// Retrieve the Storage of a component for writing
+    /// Fails if component has not been registered or is already borrowed
+    #[track_caller]
+    pub fn try_get_component_mut<T: 'static + Component>(
+        &self,
+    ) -> Result<RefMut<'_, dyn StorageTrait<T>>, GerustError> {
+        self.try_storage_cell::<T>()?.try_borrow_mut()
+    }
+
+    /// Mutably borrow the Storages of several distinct components at once
+    /// e.g. `let (positions, velocities) = engine.borrow_many::<(Position, Velocity)>();`
+    /// Will panic if a component has not been registered or is already borrowed
+    #[track_caller]
+    pub fn borrow_many<S: ComponentSet>(&self) -> S::Storages<'_> {
+        self.try_borrow_many::<S>()
+            .unwrap_or_else(|err| panic!("Could not borrow components: {}", err))
     }
 
-    /// Retrieve a component in a Engine
-    /// Will unwrap if component has not been registered before
-    pub fn get_component<T: 'static + Component>(&self) -> RefMut<Storage<T>> {
-        self.components
+    /// Mutably borrow the Storages of several distinct components at once
+    /// Fails if a component has not been registered or is already borrowed
+    #[track_caller]
+    pub fn try_borrow_many<S: ComponentSet>(&self) -> Result<S::Storages<'_>, GerustError> {
+        S::try_borrow_many(self)
+    }
+
+    /// Get when the component of an entity was added and last changed
+    /// Will panic if component has not been registered or is already mutably borrowed
+    #[track_caller]
+    pub fn component_ticks<T: 'static + Component>(
+        &self,
+        index: EntityIndex,
+    ) -> Option<ComponentTicks> {
+        self.try_get_tracked::<T>()
+            .unwrap_or_else(|err| panic!("Could not get component: {}", err))
+            .storage
+            .ticks(index)
+    }
+
+    #[track_caller]
+    pub(crate) fn try_get_tracked<T: 'static + Component>(
+        &self,
+    ) -> Result<Ref<'_, TrackedStorage<T>>, GerustError> {
+        self.try_storage_cell::<T>()?.try_borrow_tracked()
+    }
+
+    #[track_caller]
+    pub(crate) fn try_get_tracked_mut<T: 'static + Component>(
+        &self,
+    ) -> Result<RefMut<'_, TrackedStorage<T>>, GerustError> {
+        self.try_storage_cell::<T>()?.try_borrow_tracked_mut()
+    }
+
+    /// Get the current change tick, given to changes made outside of systems
+    pub fn change_tick(&self) -> Tick {
+        self.change_tick.load(Ordering::Relaxed)
+    }
+
+    /// Get a tick for a system run, later changes get a greater tick
+    pub(crate) fn next_change_tick(&self) -> Tick {
+        self.change_tick.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Ticks of code running outside of any function system, which sees every
+    /// component as added and changed
+    pub(crate) fn outside_ticks(&self) -> SystemTicks {
+        let this_run = self.change_tick();
+        SystemTicks {
+            // The oldest tick there is, whatever the ticks wrapped around to
+            last_run: this_run.wrapping_add(1),
+            this_run,
+        }
+    }
+
+    /// Clamp the ticks kept by components and systems, so that they can keep
+    /// being compared once change_tick wraps around
+    fn clamp_change_ticks(&mut self, change_tick: Tick) {
+        for storage in self.components.values() {
+            storage.clamp_ticks(change_tick);
+        }
+        for schedule in &self.schedules {
+            schedule.clamp_change_ticks(change_tick);
+        }
+        self.last_check_tick = change_tick;
+    }
+
+    fn try_storage_cell<T: 'static + Component>(&self) -> Result<&StorageCell<T>, GerustError> {
+        Ok(self
+            .components
             .get(&TypeId::of::<T>())
-            .expect("Could not get component, has it been registered ?")
-            .downcast_ref::<RefCell<Storage<T>>>()
-            .unwrap()
-            .borrow_mut()
+            .ok_or_else(not_registered::<T>)?
+            .as_any()
+            .downcast_ref::<StorageCell<T>>()
+            .unwrap())
+    }
+
+    /// Query every entity having the components requested by Q
+    /// e.g. `engine.query::<(&Position, &mut Velocity)>()`
+    /// Will panic if a component has not been registered or is already mutably borrowed
+    #[track_caller]
+    pub fn query<Q: Fetch>(&self) -> Query<'_, Q> {
+        Query::new(self)
+    }
+
+    /// Query every entity having the components requested by Q and matching the filter F
+    /// e.g. `engine.query_filtered::<&Position, Without<Velocity>>()`
+    #[track_caller]
+    pub fn query_filtered<Q: Fetch, F: QueryFilter>(&self) -> Query<'_, Q, F> {
+        Query::new(self)
     }
 
+    /// Get the mask of a component
+    /// Will panic if component has not been registered before
     pub fn get_mask<T: 'static + Component>(&self) -> ComponentMask {
-        self.component_masks[&TypeId::of::<T>()]
+        self.try_get_mask::<T>()
+            .unwrap_or_else(|err| panic!("Could not get mask: {}", err))
     }
 
-    pub fn register_system<T: 'static + System>(&mut self, system: T) {
-        self.systems.push(Box::new(system));
+    /// Get the mask of a component
+    /// Fails if component has not been registered before
+    pub fn try_get_mask<T: 'static + Component>(&self) -> Result<ComponentMask, GerustError> {
+        self.component_masks
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .ok_or_else(not_registered::<T>)
+    }
+
+    /// Register a system run at the fixed update rate, in Stage::Update unless placed elsewhere
+    /// Either a System implementation or a function taking SystemParams
+    /// e.g. `engine.register_system(apply_velocity.named("apply_velocity").after("gravity"))`
+    /// Will panic if two parameters of a function conflict, see FunctionSystem::new
+    pub fn register_system<M>(&mut self, system: impl IntoSystemDescriptor<M>) {
+        self.add_system(system.into_descriptor(), Stage::Update);
+    }
+
+    /// Register a system run once per rendered frame, in Stage::Render unless placed elsewhere
+    /// Time::alpha tells how far the frame is between two fixed updates
+    /// The canvas is presented after the Render stage, systems drawing on it run after "render_sprites"
+    pub fn register_render_system<M>(&mut self, system: impl IntoSystemDescriptor<M>) {
+        self.add_system(system.into_descriptor(), Stage::Render);
+    }
+
+    fn add_system(&mut self, system: SystemDescriptor, default_stage: Stage) {
+        let stage = system.stage.unwrap_or(default_stage);
+        self.schedules[stage.index()].add(system);
+    }
+
+    /// Order the systems of every stage along their constraints
+    /// Done before running systems, call it to check the constraints early
+    pub fn build_schedule(&mut self) -> Result<(), GerustError> {
+        for schedule in self.schedules.iter_mut() {
+            schedule.build()?;
+        }
+        Ok(())
     }
 
-    fn update_ecs(&mut self, events: &[Event]) -> Result<UpdateStatus, String> {
-        for system in self.systems.iter() {
-            match system.update(self, events) {
-                Ok(UpdateStatus::Exit) => return Ok(UpdateStatus::Exit),
-                Err(err) => return Err(err),
-                _ => {}
+    /// Get the configuration of the game loop
+    pub fn loop_config(&self) -> LoopConfig {
+        self.loop_config
+    }
+
+    /// Set the configuration of the game loop
+    /// Will panic if config is invalid, see LoopConfig::validate
+    pub fn set_loop_config(&mut self, config: LoopConfig) {
+        if let Err(err) = self.try_set_loop_config(config) {
+            panic!("Could not set loop config: {}", err);
+        }
+    }
+
+    /// Set the configuration of the game loop
+    /// Fails if config is invalid, see LoopConfig::validate
+    pub fn try_set_loop_config(&mut self, config: LoopConfig) -> Result<(), GerustError> {
+        config.validate()?;
+        self.loop_config = config;
+        Ok(())
+    }
+
+    /// Get a buffer recording structural changes to apply at the next sync point
+    /// Systems should use it to spawn, despawn, add or remove components while iterating
+    pub fn commands(&self) -> Commands<'_> {
+        Commands::new(self)
+    }
+
+    /// Apply every recorded command, including the ones recorded while applying
+    /// The Engine already does this after every batch of systems
+    pub fn apply_commands(&self) {
+        loop {
+            let commands = self.commands.take();
+            if commands.is_empty() {
+                return;
+            }
+            for command in commands {
+                command(self);
+            }
+        }
+    }
+
+    pub(crate) fn command_queue(&self) -> &CommandQueue {
+        &self.commands
+    }
+
+    /// Run one fixed update of every system with the given events, stage by stage
+    /// Event queues are updated first, so events are kept for two steps
+    /// Stops at the first system asking to exit or failing
+    pub fn step(&mut self, events: &[Event]) -> Result<UpdateStatus, GerustError> {
+        self.update_events();
+        self.fixed_update(events)
+    }
+
+    /// Run the fixed updates of a frame, only the first one with the given events
+    /// Event queues are updated once, so render systems see the events of every update
+    fn frame_updates(&mut self, steps: u32, events: &[Event]) -> Result<UpdateStatus, GerustError> {
+        self.update_events();
+        for step in 0..steps {
+            let events = if step == 0 { events } else { &[] };
+            if let UpdateStatus::Exit = self.fixed_update(events)? {
+                return Ok(UpdateStatus::Exit);
+            }
+        }
+        Ok(UpdateStatus::Continue)
+    }
+
+    fn update_events(&self) {
+        for update in &self.event_updates {
+            update(self);
+        }
+    }
+
+    fn fixed_update(&mut self, events: &[Event]) -> Result<UpdateStatus, GerustError> {
+        self.build_schedule()?;
+        let change_tick = self.change_tick();
+        // Removals are kept until every system had a fixed update to see them
+        for storage in self.components.values() {
+            storage.clear_removed(self.last_step_tick, change_tick);
+        }
+        self.last_step_tick = change_tick;
+        if change_tick.wrapping_sub(self.last_check_tick) >= CHECK_TICK_THRESHOLD {
+            self.clamp_change_ticks(change_tick);
+        }
+        self.resource_mut::<Time>()
+            .advance_tick(self.loop_config.timestep());
+        self.resource_mut::<InputEvents>().set(events);
+        for stage in Stage::FIXED_UPDATE {
+            if let UpdateStatus::Exit = self.schedules[stage.index()].run(self, events)? {
+                return Ok(UpdateStatus::Exit);
             }
         }
         Ok(UpdateStatus::Continue)
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
-        let delay = Duration::from_secs_f64(1. / FRAMERATE);
+    /// Run n fixed updates of every system without any event
+    /// Stops early if a system asks to exit or fails
+    pub fn step_n(&mut self, n: usize) -> Result<UpdateStatus, GerustError> {
+        for _ in 0..n {
+            if let UpdateStatus::Exit = self.step(&[])? {
+                return Ok(UpdateStatus::Exit);
+            }
+        }
+        Ok(UpdateStatus::Continue)
+    }
+
+    /// Run every render system once
+    /// delta is the real time since the last frame and alpha the interpolation
+    /// factor between the last fixed update and the next one
+    pub fn render(
+        &mut self,
+        delta: Duration,
+        alpha: f64,
+        events: &[Event],
+    ) -> Result<UpdateStatus, GerustError> {
+        self.build_schedule()?;
+        self.resource_mut::<Time>().advance_frame(delta, alpha);
+        self.resource_mut::<InputEvents>().set(events);
+        let status = self.schedules[Stage::Render.index()].run(self, events)?;
+        if let Some(mut canvas) = self.try_non_send_resource_mut::<Canvas<Window>>() {
+            canvas.present();
+        }
+        Ok(status)
+    }
+
+    /// Run the game loop until a system asks to exit or fails
+    /// Systems are updated at a fixed rate, catching up when a frame took too
+    /// long, while render systems are run once per frame
+    pub fn run(&mut self) -> Result<(), GerustError> {
+        let timestep = self.loop_config.timestep();
+        let max_lag = timestep * self.loop_config.max_catch_up_steps;
+        let mut accumulator = Duration::ZERO;
+        let mut pending_events = vec![];
+        let mut previous_frame = Instant::now();
         loop {
             let frame_start = Instant::now();
-            let events: Vec<Event> = self.events.poll_iter().collect();
-            match self.update_ecs(&events) {
-                Ok(UpdateStatus::Exit) => return Ok(()),
-                Err(err) => return Err(err),
-                _ => {}
+            let frame_time = frame_start - previous_frame;
+            previous_frame = frame_start;
+            accumulator = (accumulator + frame_time).min(max_lag);
+
+            let events: Vec<Event> = match self.events.get_mut() {
+                Some(events) => events.poll_iter().collect(),
+                None => vec![],
+            };
+            // Events are handed to the next fixed update, even if it only runs next frame
+            pending_events.extend(events.iter().cloned());
+            let mut steps = 0;
+            while accumulator >= timestep {
+                accumulator -= timestep;
+                steps += 1;
+            }
+            if steps > 0 {
+                let status = self.frame_updates(steps, &pending_events)?;
+                pending_events.clear();
+                if let UpdateStatus::Exit = status {
+                    return Ok(());
+                }
+            }
+
+            let alpha = accumulator.as_secs_f64() / timestep.as_secs_f64();
+            if let UpdateStatus::Exit = self.render(frame_time, alpha, &events)? {
+                return Ok(());
+            }
+
+            if let Some(limit) = self.loop_config.frame_rate_limit {
+                let frame_duration = Duration::from_secs_f64(1. / limit);
+                if let Some(remaining) = frame_duration.checked_sub(frame_start.elapsed()) {
+                    thread::sleep(remaining);
+                }
             }
-            thread::sleep(delay - frame_start.elapsed());
         }
     }
 }
 
+fn not_registered<T>() -> GerustError {
+    GerustError::ComponentNotRegistered {
+        type_name: any::type_name::<T>(),
+    }
+}
+
 impl Default for Engine {
     fn default() -> Self {
         Engine::new("SDL2", 640, 480).expect("Could not initialize engine")
@@ -184,35 +1061,107 @@ impl Default for Engine {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn new_engine() {
-        let engine = Engine::default();
+        let engine = Engine::headless();
 
-        assert!(engine.entities.len() == 0);
-        assert!(engine.components.len() == 0);
-        assert!(engine.next_free == 0);
+        assert!(engine.is_headless());
+        assert!(!engine.contains_non_send_resource::<Canvas<Window>>());
+        assert!(engine.contains_resource::<Time>());
+        assert!(engine.entities.borrow().is_empty());
+        // Only the built-in components are registered
+        assert!(engine.components.len() == 7 + cfg!(feature = "ttf") as usize);
+        assert!(engine.next_free.load(Ordering::Relaxed) == 0);
     }
 
     #[test]
     fn create_one_entity() {
-        let mut engine = Engine::default();
+        let engine = Engine::headless();
 
         let entity = engine.create_entity();
 
-        assert!(entity == 0);
-        assert!(engine.entities.get(&entity).is_some());
+        assert!(entity.index() == 0);
+        assert!(engine.entities.borrow().get(&entity).is_some());
     }
 
     #[test]
     fn create_two_entity() {
-        let mut engine = Engine::default();
+        let engine = Engine::headless();
 
         let entity1 = engine.create_entity();
         let entity2 = engine.create_entity();
 
-        assert!(entity1 == 0);
-        assert!(entity2 == 1);
+        assert!(entity1.index() == 0);
+        assert!(entity2.index() == 1);
+    }
+
+    #[test]
+    fn despawn_entity() {
+        let engine = Engine::headless();
+        let entity = engine.create_entity();
+
+        assert!(engine.despawn_entity(entity));
+
+        assert!(!engine.is_alive(entity));
+        assert!(engine.entities.borrow().is_empty());
+    }
+
+    #[test]
+    fn despawn_entity_twice() {
+        let engine = Engine::headless();
+        let entity = engine.create_entity();
+        engine.despawn_entity(entity);
+
+        assert!(!engine.despawn_entity(entity));
+    }
+
+    #[test]
+    fn despawn_entity_conflict() {
+        let mut engine = Engine::headless();
+        engine
+            .register_component_with_storage::<BasicComponent, ArchetypeStorage<BasicComponent>>();
+        let parent = engine.create_entity();
+        let child = engine.create_entity();
+        engine.add_entity_component(child, BasicComponent::new());
+        engine.set_parent(child, parent);
+
+        let basics = engine.get_component_ref::<BasicComponent>();
+        let result = engine.try_despawn_entity(parent);
+        drop(basics);
+
+        assert!(matches!(result, Err(GerustError::BorrowConflict { .. })));
+        assert!(engine.is_alive(parent) && engine.parent(child) == Some(parent));
+        assert!(engine.despawn_entity(parent));
+        assert!(engine.parent(child).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Could not despawn entity")]
+    fn despawn_borrowed_entity() {
+        let mut engine = Engine::headless();
+        engine.register_component::<BasicComponent>();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, BasicComponent::new());
+
+        let _basics = engine.get_component_ref::<BasicComponent>();
+        engine.despawn_entity(entity);
+    }
+
+    #[test]
+    fn recycle_despawned_index() {
+        let engine = Engine::headless();
+        let entity1 = engine.create_entity();
+        engine.despawn_entity(entity1);
+
+        let entity2 = engine.create_entity();
+
+        assert!(entity2.index() == entity1.index());
+        assert!(entity2.generation() == entity1.generation() + 1);
+        assert!(engine.is_alive(entity2));
+        assert!(!engine.is_alive(entity1));
     }
 
     #[derive(Debug)]
@@ -226,54 +1175,396 @@ mod test {
 
     #[test]
     fn register_one_component() {
-        let mut engine = Engine::default();
+        let mut engine = Engine::headless();
+        let built_in = engine.components.len();
 
         engine.register_component::<BasicComponent>();
 
-        assert!(engine.components.len() == 1);
-        assert!(engine.component_masks[&TypeId::of::<BasicComponent>()] == 1);
+        assert!(engine.components.len() == built_in + 1);
+        assert!(
+            engine.component_masks[&TypeId::of::<BasicComponent>()]
+                == ComponentMask::with_bit(built_in)
+        );
     }
 
     #[test]
     fn get_one_component() {
-        let mut engine = Engine::default();
+        let mut engine = Engine::headless();
 
         engine.register_component::<BasicComponent>();
 
-        engine.get_component::<BasicComponent>();
+        engine.get_component_ref::<BasicComponent>();
     }
 
     #[test]
-    #[should_panic(expected = "Could not get component, has it been registered ?")]
+    #[should_panic(expected = "Component gerust::test::BasicComponent has not been registered")]
     fn get_component_not_registered() {
-        let engine = Engine::default();
+        let engine = Engine::headless();
 
-        engine.get_component::<BasicComponent>();
+        engine.get_component_ref::<BasicComponent>();
     }
 
     #[test]
     fn add_entity_component() {
-        let mut engine = Engine::default();
+        let mut engine = Engine::headless();
         engine.register_component::<BasicComponent>();
         let entity = engine.create_entity();
 
         engine.add_entity_component(entity, BasicComponent::new());
 
         assert!(
-            engine.entities[&entity].components_mask()
-                == engine.component_masks[&TypeId::of::<BasicComponent>()]
+            engine.entities.borrow()[&entity].components_mask()
+                == &engine.component_masks[&TypeId::of::<BasicComponent>()]
         );
     }
 
+    #[test]
+    fn despawn_entity_removes_components() {
+        let mut engine = Engine::headless();
+        engine.register_component::<BasicComponent>();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, BasicComponent::new());
+
+        engine.despawn_entity(entity);
+
+        assert!(engine
+            .get_component_ref::<BasicComponent>()
+            .try_get(entity)
+            .is_err());
+    }
+
+    #[test]
+    fn register_sparse_set_component() {
+        let mut engine = Engine::headless();
+        engine.register_component_with_storage::<BasicComponent, SparseSet<BasicComponent>>();
+        let entity = engine.create_entity();
+
+        engine.add_entity_component(entity, BasicComponent::new());
+
+        assert!(engine.query::<&BasicComponent>().len() == 1);
+        engine.despawn_entity(entity);
+        assert!(engine.query::<&BasicComponent>().is_empty());
+    }
+
+    struct Health(u32);
+    impl Component for Health {}
+
+    #[test]
+    fn register_archetype_component() {
+        let mut engine = Engine::headless();
+        engine
+            .register_component_with_storage::<BasicComponent, ArchetypeStorage<BasicComponent>>();
+        engine.register_component_with_storage::<Health, ArchetypeStorage<Health>>();
+        let entity1 = engine.create_entity();
+        let entity2 = engine.create_entity();
+        engine.add_entity_component(entity1, BasicComponent::new());
+        engine.add_entity_component(entity2, BasicComponent::new());
+        engine.add_entity_component(entity2, Health(2));
+
+        engine.add_entity_component(entity1, Health(1));
+        engine.remove_entity_component::<BasicComponent>(entity2);
+
+        assert!(engine.query::<&BasicComponent>().entities() == [entity1]);
+        assert!(engine.query::<&Health>().len() == 2);
+        assert!(engine.get_component_ref::<Health>().get(entity1).0 == 1);
+        assert!(engine.get_component_ref::<Health>().get(entity2).0 == 2);
+        engine.despawn_entity(entity1);
+        assert!(engine.query::<&Health>().entities() == [entity2]);
+    }
+
     #[test]
     fn remove_entity_component() {
-        let mut engine = Engine::default();
+        let mut engine = Engine::headless();
         engine.register_component::<BasicComponent>();
         let entity = engine.create_entity();
         engine.add_entity_component(entity, BasicComponent::new());
 
-        engine.remove_entity_component::<BasicComponent>(entity);
+        let removed = engine.remove_entity_component::<BasicComponent>(entity);
+
+        assert!(removed.is_some());
+        assert!(engine.entities.borrow()[&entity]
+            .components_mask()
+            .is_empty());
+        assert!(engine
+            .get_component_ref::<BasicComponent>()
+            .try_get(entity)
+            .is_err());
+    }
+
+    #[test]
+    fn remove_missing_entity_component() {
+        let mut engine = Engine::headless();
+        engine.register_component::<BasicComponent>();
+        let entity = engine.create_entity();
+
+        assert!(engine
+            .remove_entity_component::<BasicComponent>(entity)
+            .is_none());
+    }
+
+    struct ExitAfter(u32, AtomicU32);
+    impl System for ExitAfter {
+        fn update(&self, _: &Engine, _: &[Event]) -> Result<UpdateStatus, GerustError> {
+            if self.1.fetch_add(1, Ordering::Relaxed) + 1 == self.0 {
+                return Ok(UpdateStatus::Exit);
+            }
+            Ok(UpdateStatus::Continue)
+        }
+    }
+
+    struct RecordTime(Arc<Mutex<Vec<Time>>>);
+    impl System for RecordTime {
+        fn update(&self, engine: &Engine, _: &[Event]) -> Result<UpdateStatus, GerustError> {
+            self.0.lock().unwrap().push(*engine.resource::<Time>());
+            Ok(UpdateStatus::Continue)
+        }
+    }
+
+    #[test]
+    fn step_advances_time() {
+        let mut engine = Engine::headless();
+        engine.set_loop_config(LoopConfig {
+            update_rate: 100.,
+            ..LoopConfig::default()
+        });
+
+        engine.step_n(3).unwrap();
+
+        let time = engine.resource::<Time>();
+        assert!(time.delta() == Duration::from_millis(10));
+        assert!(time.elapsed() == Duration::from_millis(30));
+        assert!(time.tick_count() == 3);
+    }
+
+    #[test]
+    fn render_runs_render_systems() {
+        let mut engine = Engine::headless();
+        let times = Arc::new(Mutex::new(vec![]));
+        engine.register_render_system(RecordTime(times.clone()));
+        engine.step(&[]).unwrap();
+
+        engine.render(Duration::from_millis(7), 0.25, &[]).unwrap();
+
+        let times = times.lock().unwrap();
+        let time = times[0];
+        assert!(times.len() == 1);
+        assert!(time.frame_count() == 1);
+        assert!(time.tick_count() == 1);
+        assert!(time.delta() == Duration::from_millis(7));
+        assert!(time.alpha() == 0.25);
+    }
+
+    struct Score(u32);
+
+    #[test]
+    fn insert_resource() {
+        let mut engine = Engine::headless();
+
+        engine.insert_resource(Score(1));
+        engine.resource_mut::<Score>().0 += 1;
+
+        assert!(engine.resource::<Score>().0 == 2);
+        assert!(engine.remove_resource::<Score>().is_some());
+        assert!(engine.try_resource::<Score>().is_none());
+    }
+
+    #[test]
+    fn step_updates_input_events() {
+        let mut engine = Engine::headless();
+
+        engine.step(&[Event::Quit { timestamp: 0 }]).unwrap();
+
+        assert!(engine.resource::<InputEvents>().len() == 1);
+    }
+
+    #[test]
+    fn try_get_component_not_registered() {
+        let engine = Engine::headless();
+
+        assert!(matches!(
+            engine.try_get_component_ref::<BasicComponent>(),
+            Err(GerustError::ComponentNotRegistered { .. })
+        ));
+    }
+
+    #[test]
+    fn shared_component_borrows() {
+        let mut engine = Engine::headless();
+        engine.register_component::<BasicComponent>();
+
+        let storage1 = engine.get_component_ref::<BasicComponent>();
+        let storage2 = engine.get_component_ref::<BasicComponent>();
+
+        assert!(storage1.len() == storage2.len());
+    }
+
+    #[test]
+    fn borrow_many() {
+        let mut engine = Engine::headless();
+        engine.register_component::<BasicComponent>();
+        engine.register_component::<Health>();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Health(1));
+
+        let (mut basics, mut healths) = engine.borrow_many::<(BasicComponent, Health)>();
+        basics.add_entity(entity, BasicComponent::new());
+        healths.get_mut(entity).0 += 1;
+
+        assert!(healths.get(entity).0 == 2);
+    }
+
+    #[test]
+    fn borrow_conflict_names_holder() {
+        let mut engine = Engine::headless();
+        engine.register_component::<BasicComponent>();
+        engine.register_component::<Health>();
+
+        let _healths = engine.get_component_mut::<Health>();
+        let line = line!() - 1;
+        let err = engine
+            .try_borrow_many::<(BasicComponent, Health)>()
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(&err, GerustError::BorrowConflict { borrowed_at, .. }
+            if borrowed_at.len() == 1 && borrowed_at[0].file() == file!() && borrowed_at[0].line() == line)
+        );
+        assert!(err
+            .to_string()
+            .contains("gerust::test::Health is already borrowed"));
+        assert!(engine.try_get_component_mut::<BasicComponent>().is_ok());
+    }
+
+    #[test]
+    fn borrow_conflict_lists_live_borrows() {
+        let mut engine = Engine::headless();
+        engine.register_component::<Health>();
+
+        let healths1 = engine.get_component_ref::<Health>();
+        let healths2 = engine.get_component_ref::<Health>();
+        let line = line!() - 1;
+        assert!(matches!(
+            engine.try_get_component_mut::<Health>(),
+            Err(GerustError::BorrowConflict { borrowed_at, .. }) if borrowed_at.len() == 2
+        ));
+        drop(healths1);
+        let err = engine.try_get_component_mut::<Health>().err().unwrap();
+        drop(healths2);
+
+        assert!(
+            matches!(&err, GerustError::BorrowConflict { borrowed_at, .. }
+            if borrowed_at.len() == 1 && borrowed_at[0].line() == line)
+        );
+        assert!(engine.try_get_component_mut::<Health>().is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "Component gerust::test::Health has not been registered")]
+    fn get_mask_not_registered() {
+        let engine = Engine::headless();
+
+        engine.get_mask::<Health>();
+    }
+
+    #[test]
+    fn try_add_entity_component_despawned() {
+        let mut engine = Engine::headless();
+        engine.register_component::<BasicComponent>();
+        let entity = engine.create_entity();
+        engine.despawn_entity(entity);
+
+        let result = engine.try_add_entity_component(entity, BasicComponent::new());
+
+        assert!(matches!(result, Err(GerustError::NoSuchEntity(index)) if index == entity));
+    }
+
+    #[test]
+    fn try_add_entity_component_conflict() {
+        let mut engine = Engine::headless();
+        engine
+            .register_component_with_storage::<BasicComponent, ArchetypeStorage<BasicComponent>>();
+        engine.register_component_with_storage::<Health, ArchetypeStorage<Health>>();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, BasicComponent::new());
+
+        let basics = engine.get_component_ref::<BasicComponent>();
+        let result = engine.try_add_entity_component(entity, Health(1));
+        drop(basics);
+
+        assert!(matches!(result, Err(GerustError::BorrowConflict { .. })));
+        assert!(engine.query::<&Health>().is_empty());
+        assert!(engine.query::<&BasicComponent>().entities() == [entity]);
+        engine.add_entity_component(entity, Health(1));
+        assert!(engine.query::<(&BasicComponent, &Health)>().entities() == [entity]);
+    }
+
+    #[test]
+    fn try_remove_entity_component_conflict() {
+        let mut engine = Engine::headless();
+        engine
+            .register_component_with_storage::<BasicComponent, ArchetypeStorage<BasicComponent>>();
+        engine.register_component_with_storage::<Health, ArchetypeStorage<Health>>();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, BasicComponent::new());
+        engine.add_entity_component(entity, Health(1));
+
+        let basics = engine.get_component_ref::<BasicComponent>();
+        let result = engine.try_remove_entity_component::<Health>(entity);
+        drop(basics);
+
+        assert!(matches!(result, Err(GerustError::BorrowConflict { .. })));
+        assert!(engine.query::<(&BasicComponent, &Health)>().entities() == [entity]);
+        assert!(matches!(
+            engine.try_remove_entity_component::<Health>(entity),
+            Ok(Some(Health(1)))
+        ));
+        assert!(engine.query::<&Health>().is_empty());
+        assert!(engine.query::<&BasicComponent>().entities() == [entity]);
+    }
+
+    struct Failing;
+    impl System for Failing {
+        fn update(&self, _: &Engine, _: &[Event]) -> Result<UpdateStatus, GerustError> {
+            Err("broken".into())
+        }
+    }
+
+    #[test]
+    fn system_failed() {
+        let mut engine = Engine::headless();
+        engine.register_system(Failing);
+
+        let result = engine.step(&[]);
+
+        assert!(
+            matches!(result, Err(GerustError::SystemFailed { system, .. }) if system.ends_with("Failing"))
+        );
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut engine = Engine::headless();
+        engine.insert_resource(Vec::<Stage>::new());
+        engine.register_system(
+            (|mut log: ResMut<Vec<Stage>>| log.push(Stage::PostUpdate)).in_stage(Stage::PostUpdate),
+        );
+        engine.register_system(|mut log: ResMut<Vec<Stage>>| log.push(Stage::Update));
+        engine.register_system(
+            (|mut log: ResMut<Vec<Stage>>| log.push(Stage::PreUpdate)).in_stage(Stage::PreUpdate),
+        );
+
+        engine.step(&[]).unwrap();
+
+        assert!(*engine.resource::<Vec<Stage>>() == Stage::FIXED_UPDATE);
+    }
+
+    #[test]
+    fn step_n_stops_on_exit() {
+        let mut engine = Engine::headless();
+        engine.register_system(ExitAfter(3, AtomicU32::new(0)));
 
-        assert!(engine.entities[&entity].components_mask() == 0);
+        assert!(matches!(engine.step_n(2), Ok(UpdateStatus::Continue)));
+        assert!(matches!(engine.step_n(5), Ok(UpdateStatus::Exit)));
     }
 }
@@ -0,0 +1,230 @@
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+
+const WORD_BITS: usize = 64;
+
+/// A growable set of component bits
+/// The first 64 bits are stored inline, so games with few components never allocate
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ComponentMask {
+    first: u64,
+    /// Words after the first one, never ends with a zero word so that
+    /// equal masks always have the same representation
+    rest: Vec<u64>,
+}
+
+impl ComponentMask {
+    /// Create an empty mask
+    pub fn new() -> ComponentMask {
+        ComponentMask::default()
+    }
+
+    /// Create a mask containing a single bit
+    pub fn with_bit(bit: usize) -> ComponentMask {
+        let mut mask = ComponentMask::new();
+        mask.insert(bit);
+        mask
+    }
+
+    /// Number of words used by the mask
+    fn len(&self) -> usize {
+        self.rest.len() + 1
+    }
+
+    fn word(&self, i: usize) -> u64 {
+        match i {
+            0 => self.first,
+            _ => self.rest.get(i - 1).copied().unwrap_or(0),
+        }
+    }
+
+    fn word_mut(&mut self, i: usize) -> &mut u64 {
+        match i {
+            0 => &mut self.first,
+            _ => {
+                if self.rest.len() < i {
+                    self.rest.resize(i, 0);
+                }
+                &mut self.rest[i - 1]
+            }
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.rest.last() == Some(&0) {
+            self.rest.pop();
+        }
+    }
+
+    /// Set a bit in the mask
+    pub fn insert(&mut self, bit: usize) {
+        *self.word_mut(bit / WORD_BITS) |= 1 << (bit % WORD_BITS);
+    }
+
+    /// Check if a bit is set in the mask
+    pub fn contains(&self, bit: usize) -> bool {
+        self.word(bit / WORD_BITS) & (1 << (bit % WORD_BITS)) != 0
+    }
+
+    /// Check if every bit of self is also set in other
+    pub fn is_subset(&self, other: &ComponentMask) -> bool {
+        self.first & !other.first == 0
+            && self
+                .rest
+                .iter()
+                .enumerate()
+                .all(|(i, word)| word & !other.word(i + 1) == 0)
+    }
+
+    /// Check if self and other share at least one bit
+    pub fn intersects(&self, other: &ComponentMask) -> bool {
+        self.first & other.first != 0
+            || self
+                .rest
+                .iter()
+                .zip(other.rest.iter())
+                .any(|(a, b)| a & b != 0)
+    }
+
+    /// Iterate over the set bits, in increasing order
+    pub fn bits(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).flat_map(move |i| {
+            let word = self.word(i);
+            (0..WORD_BITS)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * WORD_BITS + bit)
+        })
+    }
+
+    /// Check if no bit is set
+    pub fn is_empty(&self) -> bool {
+        self.first == 0 && self.rest.is_empty()
+    }
+
+    /// Clear every bit of other from self
+    pub fn remove(&mut self, other: &ComponentMask) {
+        self.first &= !other.first;
+        for (word, other) in self.rest.iter_mut().zip(other.rest.iter()) {
+            *word &= !other;
+        }
+        self.trim();
+    }
+}
+
+impl BitAnd for &ComponentMask {
+    type Output = ComponentMask;
+
+    fn bitand(self, other: &ComponentMask) -> ComponentMask {
+        let mut mask = ComponentMask {
+            first: self.first & other.first,
+            rest: self
+                .rest
+                .iter()
+                .zip(other.rest.iter())
+                .map(|(a, b)| a & b)
+                .collect(),
+        };
+        mask.trim();
+        mask
+    }
+}
+
+impl BitAnd for ComponentMask {
+    type Output = ComponentMask;
+
+    fn bitand(self, other: ComponentMask) -> ComponentMask {
+        &self & &other
+    }
+}
+
+impl BitOrAssign<&ComponentMask> for ComponentMask {
+    fn bitor_assign(&mut self, other: &ComponentMask) {
+        for i in 0..other.len() {
+            *self.word_mut(i) |= other.word(i);
+        }
+    }
+}
+
+impl BitOr for &ComponentMask {
+    type Output = ComponentMask;
+
+    fn bitor(self, other: &ComponentMask) -> ComponentMask {
+        let mut mask = self.clone();
+        mask |= other;
+        mask
+    }
+}
+
+impl BitOr for ComponentMask {
+    type Output = ComponentMask;
+
+    fn bitor(mut self, other: ComponentMask) -> ComponentMask {
+        self |= &other;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn new_mask() {
+        let mask = ComponentMask::new();
+
+        assert!(mask.is_empty());
+        assert!(!mask.contains(0));
+    }
+
+    #[test]
+    fn insert_bits() {
+        let mut mask = ComponentMask::new();
+
+        mask.insert(3);
+        mask.insert(200);
+
+        assert!(mask.contains(3));
+        assert!(mask.contains(200));
+        assert!(!mask.contains(64));
+        assert!(!mask.contains(1000));
+    }
+
+    #[test]
+    fn or_and_masks() {
+        let mask1 = ComponentMask::with_bit(1) | ComponentMask::with_bit(70);
+        let mask2 = ComponentMask::with_bit(70) | ComponentMask::with_bit(130);
+
+        let union = &mask1 | &mask2;
+        let intersection = &mask1 & &mask2;
+
+        assert!(union.contains(1) && union.contains(70) && union.contains(130));
+        assert!(intersection == ComponentMask::with_bit(70));
+    }
+
+    #[test]
+    fn bits() {
+        let mask = ComponentMask::with_bit(130) | ComponentMask::with_bit(3);
+
+        assert!(mask.bits().collect::<Vec<_>>() == [3, 130]);
+    }
+
+    #[test]
+    fn subset() {
+        let small = ComponentMask::with_bit(2) | ComponentMask::with_bit(100);
+        let big = &small | &ComponentMask::with_bit(5);
+
+        assert!(small.is_subset(&big));
+        assert!(!big.is_subset(&small));
+        assert!(ComponentMask::new().is_subset(&small));
+        assert!(!ComponentMask::with_bit(300).is_subset(&big));
+    }
+
+    #[test]
+    fn remove_keeps_equality() {
+        let mut mask = ComponentMask::with_bit(1) | ComponentMask::with_bit(150);
+
+        mask.remove(&ComponentMask::with_bit(150));
+
+        assert!(mask == ComponentMask::with_bit(1));
+        assert!(!mask.intersects(&ComponentMask::with_bit(150)));
+    }
+}
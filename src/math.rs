@@ -0,0 +1,165 @@
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// A 2D vector, used for positions, directions and scales
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub const ZERO: Vec2 = Vec2::new(0., 0.);
+    pub const ONE: Vec2 = Vec2::new(1., 1.);
+    pub const X: Vec2 = Vec2::new(1., 0.);
+    pub const Y: Vec2 = Vec2::new(0., 1.);
+
+    pub const fn new(x: f32, y: f32) -> Vec2 {
+        Vec2 { x, y }
+    }
+
+    /// Create a vector with both coordinates set to value
+    pub const fn splat(value: f32) -> Vec2 {
+        Vec2::new(value, value)
+    }
+
+    /// Unit vector pointing at angle radians, counterclockwise from the x axis
+    pub fn from_angle(angle: f32) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        Vec2::new(cos, sin)
+    }
+
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// Z coordinate of the 3D cross product, positive if other is counterclockwise from self
+    pub fn perp_dot(self, other: Vec2) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn distance(self, other: Vec2) -> f32 {
+        (self - other).length()
+    }
+
+    /// Same direction with a length of 1, None for the zero vector
+    pub fn normalize(self) -> Option<Vec2> {
+        let length = self.length();
+        if length == 0. {
+            return None;
+        }
+        Some(self / length)
+    }
+
+    /// Rotate counterclockwise by angle radians
+    pub fn rotate(self, angle: f32) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        Vec2::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+
+    /// Angle of the vector in radians, counterclockwise from the x axis
+    pub fn angle(self) -> f32 {
+        self.y.atan2(self.x)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, other: Vec2) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl SubAssign for Vec2 {
+    fn sub_assign(&mut self, other: Vec2) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Vec2;
+
+    fn neg(self) -> Vec2 {
+        Vec2::new(-self.x, -self.y)
+    }
+}
+
+/// Component-wise product
+impl Mul for Vec2 {
+    type Output = Vec2;
+
+    fn mul(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x * other.x, self.y * other.y)
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Vec2;
+
+    fn mul(self, factor: f32) -> Vec2 {
+        Vec2::new(self.x * factor, self.y * factor)
+    }
+}
+
+impl MulAssign<f32> for Vec2 {
+    fn mul_assign(&mut self, factor: f32) {
+        *self = *self * factor;
+    }
+}
+
+impl Div<f32> for Vec2 {
+    type Output = Vec2;
+
+    fn div(self, divisor: f32) -> Vec2 {
+        Vec2::new(self.x / divisor, self.y / divisor)
+    }
+}
+
+impl From<(f32, f32)> for Vec2 {
+    fn from((x, y): (f32, f32)) -> Vec2 {
+        Vec2::new(x, y)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn operations() {
+        let vec = Vec2::new(3., 4.);
+
+        assert!(vec + Vec2::ONE == Vec2::new(4., 5.));
+        assert!(vec * 2. - vec == vec);
+        assert!(vec.length() == 5.);
+        assert!(vec.normalize() == Some(Vec2::new(0.6, 0.8)));
+        assert!(Vec2::ZERO.normalize().is_none());
+    }
+
+    #[test]
+    fn rotate() {
+        let rotated = Vec2::X.rotate(FRAC_PI_2);
+
+        assert!(rotated.distance(Vec2::Y) < 1e-6);
+        assert!((rotated.angle() - FRAC_PI_2).abs() < 1e-6);
+    }
+}
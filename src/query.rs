@@ -0,0 +1,718 @@
+use crate::cell::{Ref, RefMut};
+use crate::storage::{missing_component, TrackedStorage};
+use crate::{
+    Access, Component, ComponentMask, ComponentTicks, Engine, EntityIndex, StorageTrait,
+    SystemTicks, Tick,
+};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+#[cfg(feature = "parallel")]
+use rayon::{iter::ParallelIterator, slice::ParallelSlice};
+#[cfg(feature = "parallel")]
+use std::sync::Mutex;
+
+/// A component access that can be requested from a Query
+/// Implemented for &T, &mut T, Option<&T>, Option<&mut T> and tuples of those
+pub trait Fetch {
+    /// Borrowed storages, held for as long as the Query lives
+    type State<'e>;
+
+    /// What is yielded for every matching entity
+    type Item<'q>;
+
+    /// Borrow the storages needed by the fetch
+    /// Components written through Mut items are marked as changed at ticks.this_run
+    /// Will panic if a component is already mutably borrowed
+    fn borrow(engine: &Engine, ticks: SystemTicks) -> Self::State<'_>;
+
+    /// Add the components an entity needs to match to required
+    fn update_masks(engine: &Engine, required: &mut ComponentMask);
+
+    /// Add the components read and written by the fetch to access
+    fn access(access: &mut Access);
+
+    /// Get the item of an entity
+    ///
+    /// # Safety
+    /// A mutable item must not be fetched for an entity while a previously
+    /// fetched item of that same entity is still alive, and fetches must not run
+    /// concurrently when Self isn't a ReadOnlyFetch
+    unsafe fn fetch<'q>(state: &'q Self::State<'_>, entity: EntityIndex) -> Self::Item<'q>;
+}
+
+/// A Fetch that never hands out mutable references
+/// Only read only queries can be iterated through a shared reference
+pub trait ReadOnlyFetch: Fetch {}
+
+/// A condition on the components of an entity that doesn't fetch any data
+/// Implemented for With<T>, Without<T> and tuples of those
+pub trait QueryFilter {
+    /// Add the components an entity needs to have to required and the ones
+    /// it must not have to excluded
+    fn update_masks(engine: &Engine, required: &mut ComponentMask, excluded: &mut ComponentMask);
+
+    /// Add the components read by the filter to access
+    fn access(_access: &mut Access) {}
+
+    /// Only keep the entities passing the filter, for filters that don't only depend on masks
+    fn retain(_engine: &Engine, _entities: &mut Vec<EntityIndex>, _ticks: SystemTicks) {}
+}
+
+/// Only match entities that have the component T
+pub struct With<T: Component>(PhantomData<T>);
+
+/// Only match entities that don't have the component T
+pub struct Without<T: Component>(PhantomData<T>);
+
+/// Only match entities whose component T was added since the system last ran
+pub struct Added<T: Component>(PhantomData<T>);
+
+/// Only match entities whose component T was added or changed since the system last ran
+/// A component counts as changed when replaced or written through a Query
+pub struct Changed<T: Component>(PhantomData<T>);
+
+/// Mutable access to a Storage from inside a Query
+pub struct StorageMut<'e, T: Component> {
+    _guard: RefMut<'e, TrackedStorage<T>>,
+    storage: *mut (dyn StorageTrait<T> + 'e),
+    this_run: Tick,
+}
+
+// The pointer targets the Storage owned by the guard, so it moves along with it
+unsafe impl<T: Component> Send for StorageMut<'_, T> where TrackedStorage<T>: Send {}
+
+impl<'e, T: Component> StorageMut<'e, T> {
+    #[track_caller]
+    fn new(engine: &'e Engine, ticks: SystemTicks) -> StorageMut<'e, T> {
+        let mut guard = engine
+            .try_get_tracked_mut::<T>()
+            .unwrap_or_else(|err| panic!("Could not get component: {}", err));
+        let storage = &mut *guard.storage as *mut (dyn StorageTrait<T> + 'e);
+        StorageMut {
+            _guard: guard,
+            storage,
+            this_run: ticks.this_run,
+        }
+    }
+
+    /// Get the component of an entity, marked as changed once mutably dereferenced
+    ///
+    /// # Safety
+    /// Must not be called concurrently, nor for an entity whose previously fetched
+    /// component is still alive
+    unsafe fn get<'q>(&'q self, entity: EntityIndex) -> Option<Mut<'q, T>> {
+        // get_ptr only borrows the Storage itself, never the components that
+        // previously fetched items point to
+        let (mut component, mut ticks) = (*self.storage).get_ptr(entity)?;
+        Some(Mut {
+            component: component.as_mut(),
+            ticks: ticks.as_mut(),
+            this_run: self.this_run,
+        })
+    }
+}
+
+/// Component fetched mutably by a Query
+/// Only marks the component as changed when it is mutably dereferenced
+#[derive(Debug)]
+pub struct Mut<'q, T> {
+    component: &'q mut T,
+    ticks: &'q mut ComponentTicks,
+    this_run: Tick,
+}
+
+impl<'q, T> Mut<'q, T> {
+    /// Get the mutable reference to the component, marking it as changed
+    pub fn into_inner(self) -> &'q mut T {
+        self.ticks.set_changed(self.this_run);
+        self.component
+    }
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.component
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.ticks.set_changed(self.this_run);
+        self.component
+    }
+}
+
+#[track_caller]
+fn borrow_storage<T: Component>(engine: &Engine) -> Ref<'_, dyn StorageTrait<T>> {
+    engine.get_component_ref::<T>()
+}
+
+impl<T: Component> Fetch for &T {
+    type State<'e> = Ref<'e, dyn StorageTrait<T>>;
+    type Item<'q> = &'q T;
+
+    #[track_caller]
+    fn borrow(engine: &Engine, _: SystemTicks) -> Self::State<'_> {
+        borrow_storage(engine)
+    }
+
+    fn update_masks(engine: &Engine, required: &mut ComponentMask) {
+        *required |= &engine.get_mask::<T>();
+    }
+
+    fn access(access: &mut Access) {
+        access.add_component_read::<T>();
+    }
+
+    unsafe fn fetch<'q>(state: &'q Self::State<'_>, entity: EntityIndex) -> Self::Item<'q> {
+        state.get(entity)
+    }
+}
+
+impl<T: Component> ReadOnlyFetch for &T {}
+
+impl<T: Component> Fetch for &mut T {
+    type State<'e> = StorageMut<'e, T>;
+    type Item<'q> = Mut<'q, T>;
+
+    #[track_caller]
+    fn borrow(engine: &Engine, ticks: SystemTicks) -> Self::State<'_> {
+        StorageMut::new(engine, ticks)
+    }
+
+    fn update_masks(engine: &Engine, required: &mut ComponentMask) {
+        *required |= &engine.get_mask::<T>();
+    }
+
+    fn access(access: &mut Access) {
+        access.add_component_write::<T>();
+    }
+
+    unsafe fn fetch<'q>(state: &'q Self::State<'_>, entity: EntityIndex) -> Self::Item<'q> {
+        match state.get(entity) {
+            Some(component) => component,
+            None => panic!(
+                "Could not mutably get component: {}",
+                missing_component::<T>(entity)
+            ),
+        }
+    }
+}
+
+impl<T: Component> Fetch for Option<&T> {
+    type State<'e> = Ref<'e, dyn StorageTrait<T>>;
+    type Item<'q> = Option<&'q T>;
+
+    #[track_caller]
+    fn borrow(engine: &Engine, _: SystemTicks) -> Self::State<'_> {
+        borrow_storage(engine)
+    }
+
+    fn update_masks(_: &Engine, _: &mut ComponentMask) {}
+
+    fn access(access: &mut Access) {
+        access.add_component_read::<T>();
+    }
+
+    unsafe fn fetch<'q>(state: &'q Self::State<'_>, entity: EntityIndex) -> Self::Item<'q> {
+        state.try_get(entity).ok()
+    }
+}
+
+impl<T: Component> ReadOnlyFetch for Option<&T> {}
+
+impl<T: Component> Fetch for Option<&mut T> {
+    type State<'e> = StorageMut<'e, T>;
+    type Item<'q> = Option<Mut<'q, T>>;
+
+    #[track_caller]
+    fn borrow(engine: &Engine, ticks: SystemTicks) -> Self::State<'_> {
+        StorageMut::new(engine, ticks)
+    }
+
+    fn update_masks(_: &Engine, _: &mut ComponentMask) {}
+
+    fn access(access: &mut Access) {
+        access.add_component_write::<T>();
+    }
+
+    unsafe fn fetch<'q>(state: &'q Self::State<'_>, entity: EntityIndex) -> Self::Item<'q> {
+        state.get(entity)
+    }
+}
+
+impl<T: Component> QueryFilter for With<T> {
+    fn update_masks(engine: &Engine, required: &mut ComponentMask, _: &mut ComponentMask) {
+        *required |= &engine.get_mask::<T>();
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    fn update_masks(engine: &Engine, _: &mut ComponentMask, excluded: &mut ComponentMask) {
+        *excluded |= &engine.get_mask::<T>();
+    }
+}
+
+/// Keep the entities whose ticks of component T pass the condition
+fn retain_ticks<T: Component>(
+    engine: &Engine,
+    entities: &mut Vec<EntityIndex>,
+    condition: impl Fn(ComponentTicks) -> bool,
+) {
+    let tracked = engine
+        .try_get_tracked::<T>()
+        .unwrap_or_else(|err| panic!("Could not get component: {}", err));
+    entities.retain(|entity| tracked.storage.ticks(*entity).is_some_and(&condition));
+}
+
+impl<T: Component> QueryFilter for Added<T> {
+    fn update_masks(engine: &Engine, required: &mut ComponentMask, _: &mut ComponentMask) {
+        *required |= &engine.get_mask::<T>();
+    }
+
+    fn access(access: &mut Access) {
+        access.add_component_read::<T>();
+    }
+
+    #[track_caller]
+    fn retain(engine: &Engine, entities: &mut Vec<EntityIndex>, ticks: SystemTicks) {
+        retain_ticks::<T>(engine, entities, |component| component.is_added(ticks));
+    }
+}
+
+impl<T: Component> QueryFilter for Changed<T> {
+    fn update_masks(engine: &Engine, required: &mut ComponentMask, _: &mut ComponentMask) {
+        *required |= &engine.get_mask::<T>();
+    }
+
+    fn access(access: &mut Access) {
+        access.add_component_read::<T>();
+    }
+
+    #[track_caller]
+    fn retain(engine: &Engine, entities: &mut Vec<EntityIndex>, ticks: SystemTicks) {
+        retain_ticks::<T>(engine, entities, |component| component.is_changed(ticks));
+    }
+}
+
+impl QueryFilter for () {
+    fn update_masks(_: &Engine, _: &mut ComponentMask, _: &mut ComponentMask) {}
+}
+
+macro_rules! impl_tuple_query {
+    ($($name:ident),+) => {
+        impl<$($name: Fetch),+> Fetch for ($($name,)+) {
+            type State<'e> = ($($name::State<'e>,)+);
+            type Item<'q> = ($($name::Item<'q>,)+);
+
+            #[track_caller]
+    fn borrow(engine: &Engine, ticks: SystemTicks) -> Self::State<'_> {
+                ($($name::borrow(engine, ticks),)+)
+            }
+
+            fn update_masks(engine: &Engine, required: &mut ComponentMask) {
+                $($name::update_masks(engine, required);)+
+            }
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch<'q>(state: &'q Self::State<'_>, entity: EntityIndex) -> Self::Item<'q> {
+                let ($($name,)+) = state;
+                ($($name::fetch($name, entity),)+)
+            }
+        }
+
+        impl<$($name: ReadOnlyFetch),+> ReadOnlyFetch for ($($name,)+) {}
+
+        impl<$($name: QueryFilter),+> QueryFilter for ($($name,)+) {
+            fn update_masks(
+                engine: &Engine,
+                required: &mut ComponentMask,
+                excluded: &mut ComponentMask,
+            ) {
+                $($name::update_masks(engine, required, excluded);)+
+            }
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            fn retain(engine: &Engine, entities: &mut Vec<EntityIndex>, ticks: SystemTicks) {
+                $($name::retain(engine, entities, ticks);)+
+            }
+        }
+    };
+}
+
+impl_tuple_query!(A);
+impl_tuple_query!(A, B);
+impl_tuple_query!(A, B, C);
+impl_tuple_query!(A, B, C, D);
+impl_tuple_query!(A, B, C, D, E);
+impl_tuple_query!(A, B, C, D, E, F);
+impl_tuple_query!(A, B, C, D, E, F, G);
+impl_tuple_query!(A, B, C, D, E, F, G, H);
+
+/// Typed access to every entity having a given set of components
+/// The matching entities are collected when the Query is created, so entities
+/// can be created while iterating without any borrow conflict
+pub struct Query<'e, Q: Fetch, F: QueryFilter = ()> {
+    engine: &'e Engine,
+    state: Q::State<'e>,
+    required: ComponentMask,
+    excluded: ComponentMask,
+    entities: Vec<EntityIndex>,
+    filter: PhantomData<F>,
+}
+
+impl<'e, Q: Fetch, F: QueryFilter> Query<'e, Q, F> {
+    /// Borrow the storages needed by Q and collect the matching entities
+    /// Will panic if a component has not been registered or is already mutably borrowed
+    /// Outside of function systems, every component counts as added and changed
+    #[track_caller]
+    pub fn new(engine: &'e Engine) -> Query<'e, Q, F> {
+        Query::with_ticks(engine, engine.outside_ticks())
+    }
+
+    /// Borrow the storages needed by Q and collect the matching entities
+    /// Change filters match changes made after ticks.last_run, and components written
+    /// through the query are marked as changed at ticks.this_run
+    /// Will panic if a component has not been registered or is already mutably borrowed
+    #[track_caller]
+    pub fn with_ticks(engine: &'e Engine, ticks: SystemTicks) -> Query<'e, Q, F> {
+        let mut required = ComponentMask::new();
+        let mut excluded = ComponentMask::new();
+        Q::update_masks(engine, &mut required);
+        F::update_masks(engine, &mut required, &mut excluded);
+
+        let mut entities = engine
+            .archetypes
+            .borrow()
+            .matching(&required, &excluded)
+            .flat_map(|archetype| archetype.entities().iter().copied())
+            .collect();
+        F::retain(engine, &mut entities, ticks);
+
+        Query {
+            engine,
+            state: Q::borrow(engine, ticks),
+            required,
+            excluded,
+            entities,
+            filter: PhantomData,
+        }
+    }
+
+    /// Get the entities matched by the query
+    pub fn entities(&self) -> &[EntityIndex] {
+        &self.entities
+    }
+
+    /// Get the number of matched entities
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Check if no entity matched
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Check if an entity currently has the components of the query
+    pub fn contains(&self, entity: EntityIndex) -> bool {
+        self.engine
+            .entities
+            .borrow()
+            .get(&entity)
+            .map(|entity| {
+                self.required.is_subset(entity.components_mask())
+                    && !self.excluded.intersects(entity.components_mask())
+            })
+            .unwrap_or(false)
+    }
+
+    /// Iterate over the matched entities and their items
+    pub fn iter_mut(&mut self) -> QueryIter<'_, 'e, Q> {
+        QueryIter {
+            state: &self.state,
+            entities: self.entities.iter(),
+        }
+    }
+
+    /// Get the item of an entity, None if it doesn't match the query
+    pub fn get_mut(&mut self, entity: EntityIndex) -> Option<Q::Item<'_>> {
+        if !self.contains(entity) {
+            return None;
+        }
+        // Safety: the returned item borrows self mutably, so no other item is alive
+        Some(unsafe { Q::fetch(&self.state, entity) })
+    }
+}
+
+impl<'e, Q: ReadOnlyFetch, F: QueryFilter> Query<'e, Q, F> {
+    /// Iterate over the matched entities and their items
+    pub fn iter(&self) -> QueryIter<'_, 'e, Q> {
+        QueryIter {
+            state: &self.state,
+            entities: self.entities.iter(),
+        }
+    }
+
+    /// Get the item of an entity, None if it doesn't match the query
+    pub fn get(&self, entity: EntityIndex) -> Option<Q::Item<'_>> {
+        if !self.contains(entity) {
+            return None;
+        }
+        // Safety: a read only fetch never hands out mutable references
+        Some(unsafe { Q::fetch(&self.state, entity) })
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<'e, Q: Fetch, F: QueryFilter> Query<'e, Q, F> {
+    /// Call f on every matched entity and its item from a thread pool
+    /// Entities are split in chunks of batch_size, each one fetched and processed by a single thread
+    /// Fetching goes through mutably borrowed storages, so threads take turns fetching their
+    /// chunk while the others process theirs
+    pub fn par_for_each_mut(
+        &mut self,
+        batch_size: usize,
+        f: impl Fn(EntityIndex, Q::Item<'_>) + Send + Sync,
+    ) where
+        Q::State<'e>: Send,
+    {
+        let state = Mutex::new(&mut self.state);
+        self.entities
+            .par_chunks(batch_size.max(1))
+            .for_each(|entities| {
+                let items: Vec<_> = {
+                    let guard = state.lock().unwrap();
+                    // Safety: self.state stays mutably borrowed until every thread is done,
+                    // and items can't outlive f
+                    let state = unsafe { &*(&**guard as *const Q::State<'e>) };
+                    entities
+                        .iter()
+                        // Safety: matched entities are unique, and the lock keeps fetches apart
+                        .map(|&entity| (entity, unsafe { Q::fetch(state, entity) }))
+                        .collect()
+                };
+                for (entity, item) in items {
+                    f(entity, item);
+                }
+            });
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<'e, Q: ReadOnlyFetch, F: QueryFilter> Query<'e, Q, F> {
+    /// Call f on every matched entity and its item from a thread pool
+    /// Entities are split in chunks of batch_size, each one fetched and processed by a single thread
+    pub fn par_for_each(
+        &self,
+        batch_size: usize,
+        f: impl Fn(EntityIndex, Q::Item<'_>) + Send + Sync,
+    ) where
+        Q::State<'e>: Sync,
+    {
+        let state = &self.state;
+        self.entities
+            .par_chunks(batch_size.max(1))
+            .for_each(|entities| {
+                for &entity in entities {
+                    // Safety: a read only fetch never hands out mutable references
+                    f(entity, unsafe { Q::fetch(state, entity) });
+                }
+            });
+    }
+}
+
+impl<'q, 'e, Q: Fetch, F: QueryFilter> IntoIterator for &'q mut Query<'e, Q, F> {
+    type Item = (EntityIndex, Q::Item<'q>);
+    type IntoIter = QueryIter<'q, 'e, Q>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Iterator over the entities matched by a Query
+pub struct QueryIter<'q, 'e, Q: Fetch> {
+    state: &'q Q::State<'e>,
+    entities: std::slice::Iter<'q, EntityIndex>,
+}
+
+impl<'q, 'e, Q: Fetch> Iterator for QueryIter<'q, 'e, Q> {
+    type Item = (EntityIndex, Q::Item<'q>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entity = *self.entities.next()?;
+        // Safety: matched entities are unique, so every entity is fetched once
+        Some((entity, unsafe { Q::fetch(self.state, entity) }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entities.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{new_engine, Position, Velocity};
+    use crate::GerustError;
+
+    struct Frozen;
+    impl Component for Frozen {}
+
+    fn moving_and_still() -> (Engine, EntityIndex, EntityIndex) {
+        let mut engine = new_engine();
+        engine.register_component::<Frozen>();
+
+        let moving = engine.create_entity();
+        engine.add_entity_component(moving, Position(0));
+        engine.add_entity_component(moving, Velocity(2));
+        let still = engine.create_entity();
+        engine.add_entity_component(still, Position(10));
+        engine.add_entity_component(still, Frozen);
+        (engine, moving, still)
+    }
+
+    #[test]
+    fn query_one_component() {
+        let (engine, _, _) = moving_and_still();
+
+        let query = engine.query::<&Position>();
+
+        assert!(query.len() == 2);
+        assert!(query.iter().map(|(_, position)| position.0).sum::<i32>() == 10);
+    }
+
+    #[test]
+    fn query_tuple() {
+        let (engine, moving, _) = moving_and_still();
+
+        for (_, (mut position, velocity)) in engine.query::<(&mut Position, &Velocity)>().iter_mut()
+        {
+            position.0 += velocity.0;
+        }
+
+        let query = engine.query::<(&Position, &Velocity)>();
+        assert!(query.entities() == [moving]);
+        assert!(query.get(moving) == Some((&Position(2), &Velocity(2))));
+    }
+
+    #[test]
+    fn query_optional_component() {
+        let (engine, moving, still) = moving_and_still();
+
+        let query = engine.query::<(&Position, Option<&Velocity>)>();
+
+        assert!(query.len() == 2);
+        assert!(query.get(moving).unwrap().1 == Some(&Velocity(2)));
+        assert!(query.get(still).unwrap().1.is_none());
+    }
+
+    #[test]
+    fn query_with_without() {
+        let (engine, moving, still) = moving_and_still();
+
+        let with = engine.query_filtered::<&Position, With<Frozen>>();
+        let without = engine.query_filtered::<&Position, Without<Frozen>>();
+
+        assert!(with.entities() == [still]);
+        assert!(without.entities() == [moving]);
+    }
+
+    #[test]
+    fn query_get_mut() {
+        let (engine, moving, still) = moving_and_still();
+        let mut query = engine.query::<&mut Position>();
+
+        query.get_mut(moving).unwrap().0 = 42;
+
+        assert!(query.get_mut(still).as_deref() == Some(&Position(10)));
+        assert!(query.get_mut(moving).as_deref() == Some(&Position(42)));
+    }
+
+    #[test]
+    fn query_marks_written_components() {
+        let (engine, moving, still) = moving_and_still();
+        engine.next_change_tick();
+        let mut query = engine.query::<&mut Position>();
+
+        assert!(query.get_mut(still).unwrap().0 == 10);
+        query.get_mut(moving).unwrap().0 = 1;
+        drop(query);
+
+        let changed = |entity| {
+            engine
+                .component_ticks::<Position>(entity)
+                .unwrap()
+                .changed()
+        };
+        assert!(changed(still) == 1);
+        assert!(changed(moving) == 2);
+    }
+
+    #[test]
+    fn query_despawned_entity() {
+        let (engine, moving, _) = moving_and_still();
+        let query = engine.query::<&Position>();
+
+        let result = engine.try_despawn_entity(moving);
+
+        assert!(matches!(result, Err(GerustError::BorrowConflict { .. })));
+        assert!(engine.is_alive(moving) && query.contains(moving));
+        drop(query);
+        assert!(engine.despawn_entity(moving));
+        assert!(!engine.query::<&Position>().contains(moving));
+    }
+
+    #[test]
+    fn query_removed_component() {
+        let (engine, _, still) = moving_and_still();
+        let query = engine.query_filtered::<&Position, With<Frozen>>();
+
+        engine.remove_entity_component::<Frozen>(still);
+
+        assert!(query.len() == 1);
+        assert!(!query.contains(still));
+        assert!(query.get(still).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn query_conflicting_access() {
+        let (engine, _, _) = moving_and_still();
+
+        engine.query::<(&Position, &mut Position)>();
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn query_par_for_each() {
+        let (engine, _, _) = moving_and_still();
+        for i in 0..100 {
+            let entity = engine.create_entity();
+            engine.add_entity_component(entity, Position(i));
+            engine.add_entity_component(entity, Velocity(1));
+        }
+
+        engine
+            .query::<(&mut Position, &Velocity)>()
+            .par_for_each_mut(8, |_, (mut position, velocity)| position.0 += velocity.0);
+
+        let sum = std::sync::atomic::AtomicI32::new(0);
+        engine.query::<&Position>().par_for_each(8, |_, position| {
+            sum.fetch_add(position.0, std::sync::atomic::Ordering::Relaxed);
+        });
+        // 0..100 moved by one each, plus the moving entity at 2 and the still one at 10
+        assert!(sum.into_inner() == 4950 + 100 + 2 + 10);
+    }
+}
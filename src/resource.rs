@@ -0,0 +1,244 @@
+use crate::cell::{AtomicRefCell, Ref, RefMut};
+use crate::thread_safe::{MainThread, SdlEvents, ThreadSafe};
+use sdl2::event::Event;
+use std::any::{self, Any, TypeId};
+use std::collections::HashMap;
+use std::ops::Deref;
+
+#[cfg(feature = "parallel")]
+type AnyResource = dyn Any + Send + Sync;
+
+#[cfg(not(feature = "parallel"))]
+type AnyResource = dyn Any;
+
+/// Global data that doesn't belong to any entity, such as the score or the camera
+/// Each resource is identified by its type, so there's at most one of each
+#[derive(Default)]
+pub struct Resources {
+    resources: HashMap<TypeId, Box<AnyResource>>,
+}
+
+impl Resources {
+    /// Create an empty resource map
+    pub fn new() -> Resources {
+        Resources::default()
+    }
+
+    /// Insert a resource, returning the previous one of the same type
+    pub fn insert<R: 'static + ThreadSafe>(&mut self, resource: R) -> Option<R> {
+        self.resources
+            .insert(TypeId::of::<R>(), Box::new(AtomicRefCell::new(resource)))
+            .map(|previous| Self::unbox(previous))
+    }
+
+    /// Remove a resource, returning it if it existed
+    pub fn remove<R: 'static>(&mut self) -> Option<R> {
+        self.resources
+            .remove(&TypeId::of::<R>())
+            .map(|resource| Self::unbox(resource))
+    }
+
+    /// Check if a resource exists
+    pub fn contains<R: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    /// Get a resource, None if it doesn't exist
+    /// Will panic if it is already mutably borrowed
+    pub fn try_get<R: 'static>(&self) -> Option<Ref<'_, R>> {
+        self.cell::<R>().map(|cell| cell.borrow())
+    }
+
+    /// Mutably get a resource, None if it doesn't exist
+    /// Will panic if it is already borrowed
+    pub fn try_get_mut<R: 'static>(&self) -> Option<RefMut<'_, R>> {
+        self.cell::<R>().map(|cell| cell.borrow_mut())
+    }
+
+    /// Get a resource
+    /// Will panic if it doesn't exist or is already mutably borrowed
+    pub fn get<R: 'static>(&self) -> Ref<'_, R> {
+        self.try_get()
+            .unwrap_or_else(|| panic!("Could not get resource {}", any::type_name::<R>()))
+    }
+
+    /// Mutably get a resource
+    /// Will panic if it doesn't exist or is already borrowed
+    pub fn get_mut<R: 'static>(&self) -> RefMut<'_, R> {
+        self.try_get_mut()
+            .unwrap_or_else(|| panic!("Could not mutably get resource {}", any::type_name::<R>()))
+    }
+
+    fn cell<R: 'static>(&self) -> Option<&AtomicRefCell<R>> {
+        self.resources
+            .get(&TypeId::of::<R>())
+            .map(|resource| resource.downcast_ref::<AtomicRefCell<R>>().unwrap())
+    }
+
+    fn unbox<R: 'static>(resource: Box<AnyResource>) -> R {
+        resource
+            .downcast::<AtomicRefCell<R>>()
+            .unwrap()
+            .into_inner()
+    }
+}
+
+/// Resources that can't be sent to other threads, such as the SDL Canvas
+/// They can only be used from the thread that created the Engine
+pub(crate) struct NonSendResources {
+    resources: MainThread<HashMap<TypeId, Box<dyn Any>>>,
+}
+
+impl NonSendResources {
+    pub(crate) fn new() -> NonSendResources {
+        NonSendResources {
+            resources: MainThread::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn insert<R: 'static>(&mut self, resource: R) -> Option<R> {
+        self.resources
+            .get_mut()
+            .insert(TypeId::of::<R>(), Box::new(AtomicRefCell::new(resource)))
+            .map(|previous| Self::unbox(previous))
+    }
+
+    pub(crate) fn remove<R: 'static>(&mut self) -> Option<R> {
+        self.resources
+            .get_mut()
+            .remove(&TypeId::of::<R>())
+            .map(|resource| Self::unbox(resource))
+    }
+
+    /// Will panic if called from another thread than the Engine one
+    pub(crate) fn contains<R: 'static>(&self) -> bool {
+        self.resources.get().contains_key(&TypeId::of::<R>())
+    }
+
+    /// Will panic if called from another thread than the Engine one
+    pub(crate) fn cell<R: 'static>(&self) -> Option<&AtomicRefCell<R>> {
+        self.resources
+            .get()
+            .get(&TypeId::of::<R>())
+            .map(|resource| resource.downcast_ref::<AtomicRefCell<R>>().unwrap())
+    }
+
+    fn unbox<R: 'static>(resource: Box<dyn Any>) -> R {
+        resource
+            .downcast::<AtomicRefCell<R>>()
+            .unwrap()
+            .into_inner()
+    }
+}
+
+/// The SDL events polled for the current update
+#[derive(Default)]
+pub struct InputEvents {
+    events: SdlEvents<Vec<Event>>,
+}
+
+impl InputEvents {
+    pub(crate) fn set(&mut self, events: &[Event]) {
+        self.events.0 = events.to_vec();
+    }
+}
+
+impl Deref for InputEvents {
+    type Target = [Event];
+
+    fn deref(&self) -> &[Event] {
+        &self.events.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+
+    #[test]
+    fn insert_get() {
+        let mut resources = Resources::new();
+
+        assert!(resources.insert(Score(3)).is_none());
+
+        assert!(*resources.get::<Score>() == Score(3));
+        assert!(resources.contains::<Score>());
+    }
+
+    #[test]
+    fn insert_replaces() {
+        let mut resources = Resources::new();
+        resources.insert(Score(3));
+
+        assert!(resources.insert(Score(5)) == Some(Score(3)));
+        assert!(*resources.get::<Score>() == Score(5));
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut resources = Resources::new();
+        resources.insert(Score(3));
+
+        resources.get_mut::<Score>().0 += 1;
+
+        assert!(*resources.get::<Score>() == Score(4));
+    }
+
+    #[test]
+    fn remove() {
+        let mut resources = Resources::new();
+        resources.insert(Score(3));
+
+        assert!(resources.remove::<Score>() == Some(Score(3)));
+        assert!(resources.remove::<Score>().is_none());
+        assert!(resources.try_get::<Score>().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Could not get resource")]
+    fn get_missing() {
+        let resources = Resources::new();
+
+        resources.get::<Score>();
+    }
+
+    #[test]
+    fn non_send_resource() {
+        let mut resources = NonSendResources::new();
+        resources.insert(std::rc::Rc::new(Score(3)));
+
+        assert!(resources.contains::<std::rc::Rc<Score>>());
+        assert!(**resources.cell::<std::rc::Rc<Score>>().unwrap().borrow() == Score(3));
+        assert!(resources.remove::<std::rc::Rc<Score>>().is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "can only be used from the main thread")]
+    fn non_send_resource_from_other_thread() {
+        let resources = NonSendResources::new();
+
+        let result = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    resources.contains::<Score>();
+                })
+                .join()
+        });
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "already")]
+    fn get_mut_while_borrowed() {
+        let mut resources = Resources::new();
+        resources.insert(Score(3));
+
+        let _score = resources.get::<Score>();
+        resources.get_mut::<Score>();
+    }
+}
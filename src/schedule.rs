@@ -0,0 +1,412 @@
+use crate::{Access, Engine, GerustError, Stage, SystemDescriptor, Tick, UpdateStatus};
+use sdl2::event::Event;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Range;
+
+#[cfg(feature = "parallel")]
+use crate::thread_safe::SdlEvents;
+
+/// Systems of a stage, run in registration order unless constrained otherwise
+/// With the parallel feature, consecutive systems whose Access don't conflict
+/// are run concurrently on a thread pool, otherwise they are run one by one
+pub(crate) struct Schedule {
+    stage: Stage,
+
+    /// Systems in registration order
+    systems: Vec<SystemDescriptor>,
+
+    /// Whether each system must run on the main thread
+    main_thread: Vec<bool>,
+
+    /// Indices of the systems in run order
+    order: Vec<usize>,
+
+    /// Consecutive systems of order that can run concurrently
+    batches: Vec<Range<usize>>,
+
+    /// Whether systems were added since the order was last built
+    dirty: bool,
+}
+
+impl Schedule {
+    pub(crate) fn new(stage: Stage) -> Schedule {
+        Schedule {
+            stage,
+            systems: vec![],
+            main_thread: vec![],
+            order: vec![],
+            batches: vec![],
+            dirty: false,
+        }
+    }
+
+    /// Add a system, ordered when the schedule is next built
+    pub(crate) fn add(&mut self, system: SystemDescriptor) {
+        self.systems.push(system);
+        self.dirty = true;
+    }
+
+    /// Order the systems along their constraints and group them in batches
+    /// Fails if a constraint names an unknown system or if constraints form a cycle
+    pub(crate) fn build(&mut self) -> Result<(), GerustError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let successors = self.successors()?;
+        self.order = self.sort(&successors)?;
+        self.batch(&successors);
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Systems that must run after each system
+    fn successors(&self) -> Result<Vec<Vec<usize>>, GerustError> {
+        let mut successors = vec![vec![]; self.systems.len()];
+        for (index, system) in self.systems.iter().enumerate() {
+            for name in &system.before {
+                let named = self.named(index, name)?;
+                successors[index].extend(named);
+            }
+            for name in &system.after {
+                for other in self.named(index, name)? {
+                    successors[other].push(index);
+                }
+            }
+        }
+        Ok(successors)
+    }
+
+    /// Systems with the given name, which the system at index is ordered relative to
+    fn named(&self, index: usize, name: &str) -> Result<Vec<usize>, GerustError> {
+        let named: Vec<usize> = (0..self.systems.len())
+            .filter(|&other| self.systems[other].name == name)
+            .collect();
+        if named.is_empty() {
+            return Err(GerustError::UnknownSystem {
+                system: self.systems[index].name.clone(),
+                dependency: name.to_string(),
+                stage: self.stage,
+            });
+        }
+        Ok(named)
+    }
+
+    /// Order the systems so that each one runs after all of its predecessors
+    /// Among the systems ready to run, the first registered one runs first
+    fn sort(&self, successors: &[Vec<usize>]) -> Result<Vec<usize>, GerustError> {
+        let mut predecessors = vec![0; self.systems.len()];
+        for &next in successors.iter().flatten() {
+            predecessors[next] += 1;
+        }
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..self.systems.len())
+            .filter(|&index| predecessors[index] == 0)
+            .map(Reverse)
+            .collect();
+        let mut order = Vec::with_capacity(self.systems.len());
+        while let Some(Reverse(index)) = ready.pop() {
+            order.push(index);
+            for &next in &successors[index] {
+                predecessors[next] -= 1;
+                if predecessors[next] == 0 {
+                    ready.push(Reverse(next));
+                }
+            }
+        }
+        if order.len() < self.systems.len() {
+            return Err(self.cycle(successors, &predecessors));
+        }
+        Ok(order)
+    }
+
+    /// Find a cycle among the systems sort couldn't order
+    fn cycle(&self, successors: &[Vec<usize>], predecessors: &[usize]) -> GerustError {
+        let unordered = |index: usize| predecessors[index] > 0;
+        let first = (0..self.systems.len()).find(|&index| unordered(index));
+        let mut path = vec![first.unwrap()];
+        // Every unordered system has an unordered predecessor, so walking back loops
+        let cycle = loop {
+            let last = *path.last().unwrap();
+            let previous = (0..self.systems.len())
+                .find(|&index| unordered(index) && successors[index].contains(&last))
+                .unwrap();
+            if let Some(start) = path.iter().position(|&index| index == previous) {
+                let mut cycle = path.split_off(start);
+                cycle.reverse();
+                break cycle;
+            }
+            path.push(previous);
+        };
+        let start = cycle
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &index)| index)
+            .map(|(position, _)| position)
+            .unwrap();
+        let cycle = cycle[start..]
+            .iter()
+            .chain(&cycle[..=start])
+            .map(|&index| self.systems[index].name.clone())
+            .collect();
+        GerustError::SystemOrderCycle {
+            stage: self.stage,
+            cycle,
+        }
+    }
+
+    /// Group consecutive systems of order that can run concurrently
+    fn batch(&mut self, successors: &[Vec<usize>]) {
+        self.batches.clear();
+        self.main_thread = vec![false; self.systems.len()];
+        let mut batch_access = Access::new();
+        let mut batch_closed = true;
+        for (position, &index) in self.order.iter().enumerate() {
+            let access = self.systems[index].system.access();
+            let joins_batch = match self.batches.last() {
+                Some(batch) => {
+                    !batch_closed
+                        && !access.is_exclusive()
+                        && batch_access.is_compatible(&access)
+                        && !self.order[batch.clone()]
+                            .iter()
+                            .any(|&other| successors[other].contains(&index))
+                }
+                None => false,
+            };
+            if joins_batch {
+                self.batches.last_mut().unwrap().end = position + 1;
+                batch_access.extend(&access);
+            } else {
+                self.batches.push(position..position + 1);
+                batch_access = access.clone();
+            }
+            // Later systems must see the commands of this one applied, which also
+            // keeps commands applied in system order
+            batch_closed = access.has_commands();
+            self.main_thread[index] = access.is_main_thread();
+        }
+    }
+
+    /// Run every system, applying commands between batches
+    /// Stops after the first batch with a system asking to exit or failing
+    pub(crate) fn run(
+        &self,
+        engine: &Engine,
+        events: &[Event],
+    ) -> Result<UpdateStatus, GerustError> {
+        debug_assert!(!self.dirty, "Schedule of stage {} is not built", self.stage);
+        for batch in &self.batches {
+            let systems = &self.order[batch.clone()];
+            let results = self.run_batch(engine, systems, events);
+            engine.apply_commands();
+            for (&index, result) in systems.iter().zip(results) {
+                match result {
+                    Ok(UpdateStatus::Exit) => return Ok(UpdateStatus::Exit),
+                    Err(err) => {
+                        return Err(GerustError::SystemFailed {
+                            system: self.systems[index].name.clone(),
+                            source: Box::new(err),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(UpdateStatus::Continue)
+    }
+
+    /// Clamp the ticks kept by every system, see System::clamp_change_ticks
+    pub(crate) fn clamp_change_ticks(&self, change_tick: Tick) {
+        for descriptor in &self.systems {
+            descriptor.system.clamp_change_ticks(change_tick);
+        }
+    }
+
+    fn run_system(
+        &self,
+        index: usize,
+        engine: &Engine,
+        events: &[Event],
+    ) -> Result<UpdateStatus, GerustError> {
+        self.systems[index].system.update(engine, events)
+    }
+
+    /// Run the systems one by one, stopping at the first one asking to exit or failing
+    #[cfg(not(feature = "parallel"))]
+    fn run_batch(
+        &self,
+        engine: &Engine,
+        systems: &[usize],
+        events: &[Event],
+    ) -> Vec<Result<UpdateStatus, GerustError>> {
+        let mut results = vec![];
+        for &index in systems {
+            let result = self.run_system(index, engine, events);
+            let stop = !matches!(result, Ok(UpdateStatus::Continue));
+            results.push(result);
+            if stop {
+                break;
+            }
+        }
+        results
+    }
+
+    /// Run the systems concurrently, the ones bound to the main thread on the current one
+    #[cfg(feature = "parallel")]
+    fn run_batch(
+        &self,
+        engine: &Engine,
+        systems: &[usize],
+        events: &[Event],
+    ) -> Vec<Result<UpdateStatus, GerustError>> {
+        if systems.len() == 1 {
+            return vec![self.run_system(systems[0], engine, events)];
+        }
+        let mut results: Vec<_> = systems.iter().map(|_| None).collect();
+        let events = SdlEvents(events);
+        let events = &events;
+        rayon::in_place_scope(|scope| {
+            let mut main_thread = vec![];
+            for (&index, result) in systems.iter().zip(results.iter_mut()) {
+                if self.main_thread[index] {
+                    main_thread.push((index, result));
+                } else {
+                    scope.spawn(move |_| *result = Some(self.run_system(index, engine, events.0)));
+                }
+            }
+            for (index, result) in main_thread {
+                *result = Some(self.run_system(index, engine, events.0));
+            }
+        });
+        results.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{new_engine, Position, Velocity};
+    use crate::{Commands, IntoSystemDescriptor, Query, Res, ResMut, System, Time};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Score(i32);
+
+    struct Exclusive;
+    impl System for Exclusive {
+        fn update(&self, _: &Engine, _: &[Event]) -> Result<UpdateStatus, GerustError> {
+            Ok(UpdateStatus::Continue)
+        }
+    }
+
+    fn scored_engine() -> Engine {
+        let mut engine = new_engine();
+        engine.insert_resource(Score::default());
+        for i in 0..10 {
+            let entity = engine.create_entity();
+            engine.add_entity_component(entity, Position(i));
+            engine.add_entity_component(entity, Velocity(1));
+        }
+        engine
+    }
+
+    fn add<M>(schedule: &mut Schedule, system: impl IntoSystemDescriptor<M>) {
+        schedule.add(system.into_descriptor());
+    }
+
+    #[test]
+    fn batches() {
+        let mut schedule = Schedule::new(Stage::Update);
+        add(&mut schedule, |_: Query<&Position>, _: Res<Time>| {});
+        add(&mut schedule, |_: Query<&mut Velocity>, _: Res<Time>| {});
+        add(&mut schedule, |_: Query<(&Position, &mut Velocity)>| {});
+        add(&mut schedule, |_: ResMut<Score>, _: Commands| {});
+        add(&mut schedule, |_: Query<&Position>| {});
+        add(&mut schedule, Exclusive);
+        add(&mut schedule, |_: Query<&Position>| {});
+        schedule.build().unwrap();
+
+        assert!(schedule.batches == [0..2, 2..4, 4..5, 5..6, 6..7]);
+    }
+
+    #[test]
+    fn concurrent_systems() {
+        let mut engine = scored_engine();
+        let runs = Arc::new(AtomicUsize::new(0));
+        for _ in 0..4 {
+            let runs = runs.clone();
+            engine.register_system(move |positions: Query<&Position>| {
+                assert!(positions.len() == 10);
+                runs.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        engine.register_system(|mut query: Query<(&mut Position, &Velocity)>| {
+            for (_, (mut position, velocity)) in query.iter_mut() {
+                position.0 += velocity.0;
+            }
+        });
+        engine.register_system(|positions: Query<&Position>, mut score: ResMut<Score>| {
+            score.0 = positions.iter().map(|(_, position)| position.0).sum();
+        });
+
+        engine.step_n(2).unwrap();
+
+        assert!(runs.load(Ordering::Relaxed) == 8);
+        assert!(engine.resource::<Score>().0 == 65);
+    }
+
+    fn record(log: &Arc<Mutex<Vec<&'static str>>>, name: &'static str) -> impl Fn() {
+        let log = log.clone();
+        move || log.lock().unwrap().push(name)
+    }
+
+    #[test]
+    fn ordering_constraints() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut schedule = Schedule::new(Stage::Update);
+        add(&mut schedule, record(&log, "c").named("c").after("b"));
+        add(&mut schedule, record(&log, "a").named("a"));
+        add(&mut schedule, record(&log, "b").named("b").after("a"));
+        add(&mut schedule, record(&log, "d").named("d").before("a"));
+        schedule.build().unwrap();
+
+        schedule.run(&Engine::headless(), &[]).unwrap();
+
+        assert!(*log.lock().unwrap() == ["d", "a", "b", "c"]);
+        // Ordered systems never share a batch, even with compatible accesses
+        assert!(schedule.batches.len() == 4);
+    }
+
+    #[test]
+    fn ordering_cycle() {
+        let mut schedule = Schedule::new(Stage::PostUpdate);
+        add(&mut schedule, (|| {}).named("a").after("c"));
+        add(&mut schedule, (|| {}).named("b").after("a"));
+        add(&mut schedule, (|| {}).named("c").after("b"));
+        add(&mut schedule, (|| {}).named("d").after("c"));
+
+        let err = schedule.build().unwrap_err();
+
+        assert!(
+            matches!(&err, GerustError::SystemOrderCycle { stage: Stage::PostUpdate, cycle } if cycle == &["a", "b", "c", "a"])
+        );
+        assert!(
+            err.to_string()
+                == "Systems of stage PostUpdate have cyclic ordering constraints: a -> b -> c -> a"
+        );
+    }
+
+    #[test]
+    fn ordering_unknown_system() {
+        let mut schedule = Schedule::new(Stage::Update);
+        add(&mut schedule, (|| {}).named("a").before("render"));
+
+        let err = schedule.build().unwrap_err();
+
+        assert!(
+            err.to_string()
+                == "System a is ordered relative to render, which is not in stage Update"
+        );
+    }
+}
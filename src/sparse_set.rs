@@ -0,0 +1,235 @@
+use super::{Component, ComponentTicks, EntityIndex, GerustError, StorageTrait, Tick};
+use crate::storage::missing_component;
+use std::ptr::NonNull;
+
+/// Component storage packing every component in a contiguous Vec
+/// An entity slot indexes the sparse array, which gives the position of its
+/// component in the dense array, so lookups never hash and iteration is linear
+pub struct SparseSet<T: Component> {
+    /// Position in dense of the component of each entity slot
+    sparse: Vec<Option<usize>>,
+
+    /// Packed components
+    dense: Vec<T>,
+
+    /// Ticks of each component of dense
+    ticks: Vec<ComponentTicks>,
+
+    /// Entity owning each component of dense
+    entities: Vec<EntityIndex>,
+}
+
+impl<T: Component> SparseSet<T> {
+    /// Get the position in dense of an entity component
+    fn dense_index(&self, index: EntityIndex) -> Option<usize> {
+        let dense_index = (*self.sparse.get(index.index() as usize)?)?;
+        // A recycled slot may still point to the component of a despawned entity
+        if self.entities[dense_index] == index {
+            Some(dense_index)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Component> StorageTrait<T> for SparseSet<T> {
+    fn new() -> SparseSet<T> {
+        SparseSet {
+            sparse: vec![],
+            dense: vec![],
+            ticks: vec![],
+            entities: vec![],
+        }
+    }
+
+    fn add_entity_with_ticks(&mut self, index: EntityIndex, component: T, ticks: ComponentTicks) {
+        let slot = index.index() as usize;
+        if self.sparse.len() <= slot {
+            self.sparse.resize(slot + 1, None);
+        }
+        match self.sparse[slot] {
+            // The slot is already used, either by this entity or a stale one
+            Some(dense_index) => {
+                self.dense[dense_index] = component;
+                self.ticks[dense_index] = ticks;
+                self.entities[dense_index] = index;
+            }
+            None => {
+                self.sparse[slot] = Some(self.dense.len());
+                self.dense.push(component);
+                self.ticks.push(ticks);
+                self.entities.push(index);
+            }
+        }
+    }
+
+    fn remove_entity(&mut self, index: EntityIndex) -> Option<T> {
+        let dense_index = self.dense_index(index)?;
+        self.sparse[index.index() as usize] = None;
+        self.entities.swap_remove(dense_index);
+        self.ticks.swap_remove(dense_index);
+        let component = self.dense.swap_remove(dense_index);
+        // The last component now fills the hole, its slot must point to it
+        if let Some(moved) = self.entities.get(dense_index) {
+            self.sparse[moved.index() as usize] = Some(dense_index);
+        }
+        Some(component)
+    }
+
+    fn try_get(&self, index: EntityIndex) -> Result<&T, GerustError> {
+        match self.dense_index(index) {
+            Some(dense_index) => Ok(&self.dense[dense_index]),
+            None => Err(missing_component::<T>(index)),
+        }
+    }
+
+    fn try_get_mut(&mut self, index: EntityIndex) -> Result<&mut T, GerustError> {
+        match self.dense_index(index) {
+            Some(dense_index) => Ok(&mut self.dense[dense_index]),
+            None => Err(missing_component::<T>(index)),
+        }
+    }
+
+    fn ticks(&self, index: EntityIndex) -> Option<ComponentTicks> {
+        Some(self.ticks[self.dense_index(index)?])
+    }
+
+    fn get_ptr(&mut self, index: EntityIndex) -> Option<(NonNull<T>, NonNull<ComponentTicks>)> {
+        let dense_index = self.dense_index(index)?;
+        // Safety: dense_index is in bounds of both Vecs, and as_mut_ptr doesn't borrow
+        // the other components
+        unsafe {
+            Some((
+                NonNull::new_unchecked(self.dense.as_mut_ptr().add(dense_index)),
+                NonNull::new_unchecked(self.ticks.as_mut_ptr().add(dense_index)),
+            ))
+        }
+    }
+
+    fn clamp_ticks(&mut self, change_tick: Tick) {
+        for ticks in &mut self.ticks {
+            ticks.clamp(change_tick);
+        }
+    }
+
+    fn contains(&self, index: EntityIndex) -> bool {
+        self.dense_index(index).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (EntityIndex, &T)> + '_> {
+        Box::new(self.entities.iter().copied().zip(self.dense.iter()))
+    }
+
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (EntityIndex, &mut T)> + '_> {
+        Box::new(self.entities.iter().copied().zip(self.dense.iter_mut()))
+    }
+
+    fn entities(&self) -> Box<dyn Iterator<Item = EntityIndex> + '_> {
+        Box::new(self.entities.iter().copied())
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (EntityIndex, T)> + '_> {
+        self.sparse.clear();
+        self.ticks.clear();
+        Box::new(self.entities.drain(..).zip(self.dense.drain(..)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    impl Component for Health {}
+
+    #[test]
+    fn add_entities() {
+        let mut storage: SparseSet<Health> = SparseSet::new();
+
+        storage.add_entity(EntityIndex::new(10, 0), Health(1));
+        storage.add_entity(EntityIndex::new(3, 0), Health(2));
+
+        assert!(storage.dense.len() == 2);
+        assert!(storage.get(EntityIndex::new(10, 0)) == &Health(1));
+        assert!(storage.get(EntityIndex::new(3, 0)) == &Health(2));
+    }
+
+    #[test]
+    fn remove_swaps_last() {
+        let mut storage: SparseSet<Health> = SparseSet::new();
+        storage.add_entity(EntityIndex::new(0, 0), Health(0));
+        storage.add_entity(EntityIndex::new(1, 0), Health(1));
+        storage.add_entity(EntityIndex::new(2, 0), Health(2));
+
+        assert!(storage.remove_entity(EntityIndex::new(0, 0)) == Some(Health(0)));
+
+        assert!(storage.dense == [Health(2), Health(1)]);
+        assert!(storage.get(EntityIndex::new(2, 0)) == &Health(2));
+        assert!(storage.try_get(EntityIndex::new(0, 0)).is_err());
+    }
+
+    #[test]
+    fn remove_missing() {
+        let mut storage: SparseSet<Health> = SparseSet::new();
+        storage.add_entity(EntityIndex::new(0, 0), Health(0));
+
+        assert!(storage.remove_entity(EntityIndex::new(5, 0)).is_none());
+        assert!(storage.remove_entity(EntityIndex::new(0, 1)).is_none());
+        assert!(storage.dense.len() == 1);
+    }
+
+    #[test]
+    fn stale_generation() {
+        let mut storage: SparseSet<Health> = SparseSet::new();
+        storage.add_entity(EntityIndex::new(4, 0), Health(0));
+
+        storage.add_entity(EntityIndex::new(4, 1), Health(1));
+
+        assert!(storage.dense.len() == 1);
+        assert!(storage.try_get(EntityIndex::new(4, 0)).is_err());
+        assert!(storage.get_mut(EntityIndex::new(4, 1)) == &mut Health(1));
+    }
+
+    #[test]
+    fn pointers_of_distinct_entities() {
+        let mut storage: SparseSet<Health> = SparseSet::new();
+        storage.add_entity(EntityIndex::new(0, 0), Health(0));
+        storage.add_entity(EntityIndex::new(1, 0), Health(1));
+
+        let (first, _) = storage.get_ptr(EntityIndex::new(0, 0)).unwrap();
+        let (second, _) = storage.get_ptr(EntityIndex::new(1, 0)).unwrap();
+        unsafe {
+            (*first.as_ptr()).0 += 10;
+            (*second.as_ptr()).0 += 10;
+            (*first.as_ptr()).0 += 10;
+        }
+
+        assert!(storage.dense == [Health(20), Health(11)]);
+        assert!(storage.get_ptr(EntityIndex::new(1, 1)).is_none());
+    }
+
+    #[test]
+    fn iterate_dense() {
+        let mut storage: SparseSet<Health> = SparseSet::new();
+        storage.add_entity(EntityIndex::new(7, 0), Health(7));
+        storage.add_entity(EntityIndex::new(2, 0), Health(2));
+
+        storage.iter_mut().for_each(|(_, health)| health.0 += 1);
+
+        assert!(
+            storage.iter().collect::<Vec<_>>()
+                == [
+                    (EntityIndex::new(7, 0), &Health(8)),
+                    (EntityIndex::new(2, 0), &Health(3))
+                ]
+        );
+        assert!(storage.drain().count() == 2);
+        assert!(!storage.contains(EntityIndex::new(7, 0)));
+        assert!(storage.is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{Camera2D, Component, GerustError, GlobalTransform2D, NonSendMut, Query, Res, Vec2};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::surface::Surface;
+use sdl2::video::{Window, WindowContext};
+use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+/// Handle to a texture loaded in the Textures resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+/// Textures drawn by the sprite renderer, a non-send resource of every windowed Engine
+pub struct Textures {
+    /// Created by creator, declared before it so that they are destroyed first
+    /// The creator keeps the renderer alive, and textures are only lent for less than self
+    textures: Vec<Texture<'static>>,
+    paths: HashMap<PathBuf, TextureHandle>,
+    creator: TextureCreator<WindowContext>,
+}
+
+impl Textures {
+    pub(crate) fn new(canvas: &Canvas<Window>) -> Textures {
+        Textures {
+            textures: vec![],
+            paths: HashMap::new(),
+            creator: canvas.texture_creator(),
+        }
+    }
+
+    /// Load a texture from a file, or get the one already loaded from path
+    /// BMP files are always supported, other formats such as PNG need the image feature
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<TextureHandle, GerustError> {
+        let path = path.as_ref();
+        if let Some(&handle) = self.paths.get(path) {
+            return Ok(handle);
+        }
+        let texture = self.load_file(path).map_err(GerustError::Sdl)?;
+        // Safety: the texture was created by self.creator
+        let handle = self.insert(unsafe { Self::own(texture) });
+        self.paths.insert(path.to_path_buf(), handle);
+        Ok(handle)
+    }
+
+    fn load_file(&self, path: &Path) -> Result<Texture<'_>, String> {
+        let is_bmp = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("bmp"));
+        if is_bmp {
+            let surface = Surface::load_bmp(path)?;
+            return self
+                .creator
+                .create_texture_from_surface(&surface)
+                .map_err(|e| e.to_string());
+        }
+
+        #[cfg(feature = "image")]
+        {
+            use sdl2::image::LoadTexture;
+            self.creator.load_texture(path)
+        }
+        #[cfg(not(feature = "image"))]
+        Err(format!(
+            "Can't load {}, only BMP files are supported without the image feature",
+            path.display()
+        ))
+    }
+
+    /// Create a texture from a surface, e.g. one drawn by the game
+    pub fn add_surface(&mut self, surface: &Surface) -> Result<TextureHandle, GerustError> {
+        let texture = self
+            .creator
+            .create_texture_from_surface(surface)
+            .map_err(|e| GerustError::Sdl(e.to_string()))?;
+        // Safety: the texture was created by self.creator
+        Ok(self.insert(unsafe { Self::own(texture) }))
+    }
+
+    /// Detach a texture from the borrow of the creator, so that it can be kept next to it
+    /// Safety: texture must have been created by self.creator
+    unsafe fn own(texture: Texture<'_>) -> Texture<'static> {
+        mem::transmute(texture)
+    }
+
+    fn insert(&mut self, mut texture: Texture<'static>) -> TextureHandle {
+        texture.set_blend_mode(BlendMode::Blend);
+        self.textures.push(texture);
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    pub fn get(&self, handle: TextureHandle) -> Option<&Texture<'_>> {
+        self.textures.get(handle.0)
+    }
+
+    /// Call f with a texture, e.g. to change its color modulation or its pixels
+    /// None if no texture has this handle
+    pub fn with_mut<R>(
+        &mut self,
+        handle: TextureHandle,
+        f: impl FnOnce(&mut Texture<'_>) -> R,
+    ) -> Option<R> {
+        self.textures.get_mut(handle.0).map(f)
+    }
+
+    /// Get the width and height of a texture, in pixels
+    pub fn size(&self, handle: TextureHandle) -> Option<(u32, u32)> {
+        self.get(handle).map(|texture| {
+            let query = texture.query();
+            (query.width, query.height)
+        })
+    }
+}
+
+/// Color the canvas is cleared with before drawing sprites
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClearColor(pub Color);
+
+impl Default for ClearColor {
+    fn default() -> ClearColor {
+        ClearColor(Color::BLACK)
+    }
+}
+
+/// Texture drawn centered on the GlobalTransform2D of its entity by render_sprites
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub texture: TextureHandle,
+    /// Part of the texture to draw, the whole texture if None
+    pub source: Option<Rect>,
+    /// Color multiplied with the texture, white leaves it untouched
+    pub tint: Color,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Sprites of higher layers are drawn on top, equal layers in query order
+    pub layer: i32,
+}
+
+impl Sprite {
+    pub fn new(texture: TextureHandle) -> Sprite {
+        Sprite {
+            texture,
+            source: None,
+            tint: Color::WHITE,
+            flip_x: false,
+            flip_y: false,
+            layer: 0,
+        }
+    }
+
+    pub fn with_source(self, source: Rect) -> Sprite {
+        Sprite {
+            source: Some(source),
+            ..self
+        }
+    }
+
+    pub fn with_tint(self, tint: Color) -> Sprite {
+        Sprite { tint, ..self }
+    }
+
+    pub fn with_layer(self, layer: i32) -> Sprite {
+        Sprite { layer, ..self }
+    }
+}
+
+impl Component for Sprite {}
+
+/// Where and how to draw a sprite on the canvas
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Placement {
+    destination: Rect,
+    /// Clockwise on the screen, in degrees
+    angle: f64,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+/// Place a sprite whose source is size pixels large, shears are dropped
+fn place(sprite: &Sprite, size: (u32, u32), transform: &GlobalTransform2D) -> Placement {
+    let local = transform.to_transform();
+    let size = Vec2::new(size.0 as f32, size.1 as f32) * local.scale;
+    let corner = local.translation - Vec2::new(size.x.abs(), size.y.abs()) / 2.;
+    Placement {
+        destination: Rect::new(
+            corner.x.round() as i32,
+            corner.y.round() as i32,
+            size.x.abs().round() as u32,
+            size.y.abs().round() as u32,
+        ),
+        angle: local.rotation.to_degrees() as f64,
+        flip_x: sprite.flip_x ^ (size.x < 0.),
+        flip_y: sprite.flip_y ^ (size.y < 0.),
+    }
+}
+
+/// Clear the canvas and draw every Sprite with a GlobalTransform2D, from the lowest layer up,
+/// through every Camera2D, from the lowest order up
+/// Registered in Stage::Render by Engine::new, systems drawing on top of sprites run after "render_sprites"
+pub fn render_sprites(
+    mut canvas: NonSendMut<Canvas<Window>>,
+    mut textures: NonSendMut<Textures>,
+    clear_color: Res<ClearColor>,
+    cameras: Query<&Camera2D>,
+    sprites: Query<(&Sprite, &GlobalTransform2D)>,
+) -> Result<(), GerustError> {
+    canvas.set_draw_color(clear_color.0);
+    canvas.clear();
+
+    let mut sorted: Vec<_> = sprites.iter().map(|(_, sprite)| sprite).collect();
+    sorted.sort_by_key(|(sprite, _)| sprite.layer);
+    let mut cameras: Vec<_> = cameras.iter().map(|(_, camera)| camera).collect();
+    cameras.sort_by_key(|camera| camera.order);
+    for camera in cameras {
+        canvas.set_clip_rect(camera.viewport);
+        let view = camera.view_transform();
+        for &(sprite, transform) in &sorted {
+            draw_sprite(&mut canvas, &mut textures, sprite, &view.mul(transform))?;
+        }
+    }
+    canvas.set_clip_rect(None);
+    Ok(())
+}
+
+/// Draw a sprite whose transform maps it to screen pixels
+fn draw_sprite(
+    canvas: &mut Canvas<Window>,
+    textures: &mut Textures,
+    sprite: &Sprite,
+    transform: &GlobalTransform2D,
+) -> Result<(), GerustError> {
+    let size = match (sprite.source, textures.size(sprite.texture)) {
+        (Some(source), _) => (source.width(), source.height()),
+        (None, Some(size)) => size,
+        (None, None) => return Ok(()),
+    };
+    let placement = place(sprite, size, transform);
+    textures
+        .with_mut(sprite.texture, |texture| {
+            texture.set_color_mod(sprite.tint.r, sprite.tint.g, sprite.tint.b);
+            texture.set_alpha_mod(sprite.tint.a);
+            canvas.copy_ex(
+                texture,
+                sprite.source,
+                placement.destination,
+                placement.angle,
+                None,
+                placement.flip_x,
+                placement.flip_y,
+            )
+        })
+        .unwrap_or(Ok(()))
+        .map_err(GerustError::Sdl)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Transform2D;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn place_centered() {
+        let sprite = Sprite::new(TextureHandle(0));
+        let transform = Transform2D::from_xy(100., 50.).with_scale(Vec2::new(2., 1.));
+
+        let placement = place(&sprite, (10, 20), &transform.into());
+
+        assert!(placement.destination == Rect::new(90, 40, 20, 20));
+        assert!(placement.angle == 0.);
+        assert!(!placement.flip_x && !placement.flip_y);
+    }
+
+    #[test]
+    fn place_rotated_and_mirrored() {
+        let sprite = Sprite::new(TextureHandle(0));
+        let transform = Transform2D::IDENTITY
+            .with_rotation(FRAC_PI_2)
+            .with_scale(Vec2::new(1., -1.));
+
+        let placement = place(&sprite, (10, 10), &transform.into());
+
+        assert!(placement.destination == Rect::new(-5, -5, 10, 10));
+        assert!((placement.angle - 90.).abs() < 1e-3);
+        assert!(!placement.flip_x && placement.flip_y);
+    }
+}
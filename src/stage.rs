@@ -0,0 +1,34 @@
+use std::fmt;
+
+/// Group of systems run together, stages run one after the other
+/// PreUpdate, Update and PostUpdate are run on every fixed update, Render once per frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+
+impl Stage {
+    /// Every stage, in run order
+    pub const ALL: [Stage; 4] = [
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::Render,
+    ];
+
+    /// Stages run on every fixed update, in run order
+    pub const FIXED_UPDATE: [Stage; 3] = [Stage::PreUpdate, Stage::Update, Stage::PostUpdate];
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
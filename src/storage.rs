@@ -1,28 +1,117 @@
-use super::{Component, EntityIndex};
-use std::any;
+use super::{
+    ArchetypeId, Component, ComponentTicks, Engine, EntityIndex, GerustError, ThreadSafe, Tick,
+};
+use crate::cell::{AtomicRefCell, BorrowLocations, Ref, RefMut};
+use crate::tick::Removals;
+use std::any::{self, Any};
 use std::collections::HashMap;
+use std::panic::Location;
+use std::ptr::NonNull;
 
-pub trait StorageTrait<T: Component> {
+/// Storage of every instance of a component
+/// The implementation is picked for each component when registering it
+pub trait StorageTrait<T: Component>: ThreadSafe {
     /// Create a new component Storage
-    fn new() -> Self;
+    fn new() -> Self
+    where
+        Self: Sized;
+
+    /// Add an entity component to Storage, along with when it was added and last changed
+    fn add_entity_with_ticks(&mut self, index: EntityIndex, component: T, ticks: ComponentTicks);
 
     /// Add an entity component to Storage
-    fn add_entity(&mut self, index: EntityIndex, component: T);
+    /// Changes made directly to a Storage aren't tracked, the component is dated at tick 0
+    fn add_entity(&mut self, index: EntityIndex, component: T) {
+        self.add_entity_with_ticks(index, component, ComponentTicks::new(0));
+    }
 
-    /// Remove an entity component from Storage
-    fn remove_entity(&mut self, index: EntityIndex);
+    /// Remove an entity component from Storage, returning it if it was present
+    fn remove_entity(&mut self, index: EntityIndex) -> Option<T>;
 
     /// Get a ref to an entity component from Storage
     /// Will panic if entity does not exist
-    fn get(&self, index: EntityIndex) -> &T;
+    fn get(&self, index: EntityIndex) -> &T {
+        self.try_get(index)
+            .unwrap_or_else(|err| panic!("Could not get component: {}", err))
+    }
 
     /// Get a mut ref to an entity component from Storage
     /// Will panic if entity does not exist
-    fn get_mut(&mut self, index: EntityIndex) -> &mut T;
+    fn get_mut(&mut self, index: EntityIndex) -> &mut T {
+        self.try_get_mut(index)
+            .unwrap_or_else(|err| panic!("Could not mutably get component: {}", err))
+    }
+
+    /// Get a ref to an entity component from Storage
+    /// Fails with MissingComponent if entity does not exist
+    fn try_get(&self, index: EntityIndex) -> Result<&T, GerustError>;
+
+    /// Get a mut ref to an entity component from Storage
+    /// Fails with MissingComponent if entity does not exist
+    fn try_get_mut(&mut self, index: EntityIndex) -> Result<&mut T, GerustError>;
+
+    /// Get when an entity component was added and last changed, None if entity does not exist
+    fn ticks(&self, index: EntityIndex) -> Option<ComponentTicks>;
+
+    /// Get pointers to an entity component and its ticks, None if entity does not exist
+    /// Unlike get_mut, never borrows the other components, so pointers to the components
+    /// of distinct entities can be used together until Storage is next modified
+    fn get_ptr(&mut self, index: EntityIndex) -> Option<(NonNull<T>, NonNull<ComponentTicks>)>;
+
+    /// Clamp the ticks of every component, see ComponentTicks::clamp
+    fn clamp_ticks(&mut self, change_tick: Tick);
+
+    /// Check if an entity has a component in Storage
+    fn contains(&self, index: EntityIndex) -> bool;
+
+    /// Number of components in Storage
+    fn len(&self) -> usize;
+
+    /// Check if Storage holds no component
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over every component along with its entity, in no particular order
+    fn iter(&self) -> Box<dyn Iterator<Item = (EntityIndex, &T)> + '_>;
+
+    /// Mutably iterate over every component along with its entity, in no particular order
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (EntityIndex, &mut T)> + '_>;
+
+    /// Iterate over the entities having a component in Storage
+    fn entities(&self) -> Box<dyn Iterator<Item = EntityIndex> + '_> {
+        Box::new(self.iter().map(|(index, _)| index))
+    }
+
+    /// Remove every component from Storage, returning them along with their entity
+    /// Storage is left empty even if the iterator is dropped early
+    /// Entities keep the component in their mask, use Engine::drain_component to update them
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (EntityIndex, T)> + '_>;
+
+    /// Whether the Engine should call move_to_archetype when an entity changes archetype
+    fn tracks_archetypes() -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    /// Called by the Engine before an entity holding this component, or
+    /// about to, is moved to another archetype
+    fn move_to_archetype(&mut self, _index: EntityIndex, _archetype: ArchetypeId) {}
+}
+
+/// Error of a Storage asked for the component of an entity that doesn't have one
+pub(crate) fn missing_component<T>(entity: EntityIndex) -> GerustError {
+    GerustError::MissingComponent {
+        entity,
+        type_name: any::type_name::<T>(),
+    }
 }
 
+/// Default component storage, backed by a HashMap
 pub struct Storage<T: Component> {
-    entity_components: HashMap<EntityIndex, T>,
+    pub(crate) entity_components: HashMap<EntityIndex, (T, ComponentTicks)>,
 }
 
 impl<T: Component> StorageTrait<T> for Storage<T> {
@@ -32,31 +121,252 @@ impl<T: Component> StorageTrait<T> for Storage<T> {
         }
     }
 
-    fn add_entity(&mut self, index: EntityIndex, component: T) {
-        self.entity_components.insert(index, component);
+    fn add_entity_with_ticks(&mut self, index: EntityIndex, component: T, ticks: ComponentTicks) {
+        self.entity_components.insert(index, (component, ticks));
     }
 
-    fn remove_entity(&mut self, index: EntityIndex) {
-        self.entity_components.remove(&index);
+    fn remove_entity(&mut self, index: EntityIndex) -> Option<T> {
+        self.entity_components
+            .remove(&index)
+            .map(|(component, _)| component)
     }
 
-    fn get(&self, index: EntityIndex) -> &T {
-        self.entity_components.get(&index).expect(&format!(
-            "Could not get component {} for entity {}",
-            any::type_name::<T>(),
-            index
-        ))
+    fn try_get(&self, index: EntityIndex) -> Result<&T, GerustError> {
+        self.entity_components
+            .get(&index)
+            .map(|(component, _)| component)
+            .ok_or_else(|| missing_component::<T>(index))
     }
 
-    fn get_mut(&mut self, index: EntityIndex) -> &mut T {
-        self.entity_components.get_mut(&index).expect(&format!(
-            "Could not mutably get component {} for entity {}",
-            any::type_name::<T>(),
-            index
-        ))
+    fn try_get_mut(&mut self, index: EntityIndex) -> Result<&mut T, GerustError> {
+        self.entity_components
+            .get_mut(&index)
+            .map(|(component, _)| component)
+            .ok_or_else(|| missing_component::<T>(index))
+    }
+
+    fn ticks(&self, index: EntityIndex) -> Option<ComponentTicks> {
+        self.entity_components.get(&index).map(|(_, ticks)| *ticks)
+    }
+
+    fn get_ptr(&mut self, index: EntityIndex) -> Option<(NonNull<T>, NonNull<ComponentTicks>)> {
+        self.entity_components
+            .get_mut(&index)
+            .map(|(component, ticks)| (NonNull::from(component), NonNull::from(ticks)))
+    }
+
+    fn clamp_ticks(&mut self, change_tick: Tick) {
+        for (_, ticks) in self.entity_components.values_mut() {
+            ticks.clamp(change_tick);
+        }
+    }
+
+    fn contains(&self, index: EntityIndex) -> bool {
+        self.entity_components.contains_key(&index)
+    }
+
+    fn len(&self) -> usize {
+        self.entity_components.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (EntityIndex, &T)> + '_> {
+        Box::new(
+            self.entity_components
+                .iter()
+                .map(|(index, (component, _))| (*index, component)),
+        )
+    }
+
+    fn iter_mut(&mut self) -> Box<dyn Iterator<Item = (EntityIndex, &mut T)> + '_> {
+        Box::new(
+            self.entity_components
+                .iter_mut()
+                .map(|(index, (component, _))| (*index, component)),
+        )
+    }
+
+    fn entities(&self) -> Box<dyn Iterator<Item = EntityIndex> + '_> {
+        Box::new(self.entity_components.keys().copied())
+    }
+
+    fn drain(&mut self) -> Box<dyn Iterator<Item = (EntityIndex, T)> + '_> {
+        Box::new(
+            self.entity_components
+                .drain()
+                .map(|(index, (component, _))| (index, component)),
+        )
+    }
+}
+
+/// Cell holding the storage of a component, whatever its implementation
+/// Remembers where its live borrows were made to explain borrow conflicts
+pub(crate) struct StorageCell<T: Component> {
+    storage: AtomicRefCell<TrackedStorage<T>>,
+    borrows: BorrowLocations,
+}
+
+/// A Storage along with the entities which lost their component
+pub(crate) struct TrackedStorage<T: Component> {
+    pub(crate) storage: Box<dyn StorageTrait<T>>,
+    pub(crate) removals: Removals,
+}
+
+impl<T: Component> StorageCell<T> {
+    pub(crate) fn new(storage: Box<dyn StorageTrait<T>>) -> StorageCell<T> {
+        StorageCell {
+            storage: AtomicRefCell::new(TrackedStorage {
+                storage,
+                removals: Removals::default(),
+            }),
+            borrows: BorrowLocations::default(),
+        }
+    }
+
+    /// Borrow the Storage
+    /// Fails with BorrowConflict if it is already mutably borrowed
+    #[track_caller]
+    pub(crate) fn try_borrow(&self) -> Result<Ref<'_, dyn StorageTrait<T>>, GerustError> {
+        Ok(Ref::map(self.try_borrow_tracked()?, |tracked| {
+            &*tracked.storage
+        }))
+    }
+
+    /// Mutably borrow the Storage
+    /// Fails with BorrowConflict if it is already borrowed
+    #[track_caller]
+    pub(crate) fn try_borrow_mut(&self) -> Result<RefMut<'_, dyn StorageTrait<T>>, GerustError> {
+        Ok(RefMut::map(self.try_borrow_tracked_mut()?, |tracked| {
+            &mut *tracked.storage
+        }))
+    }
+
+    /// Borrow the Storage along with its change ticks
+    /// Fails with BorrowConflict if it is already mutably borrowed
+    #[track_caller]
+    pub(crate) fn try_borrow_tracked(&self) -> Result<Ref<'_, TrackedStorage<T>>, GerustError> {
+        self.storage
+            .try_borrow_at(&self.borrows, Location::caller())
+            .ok_or_else(|| self.conflict())
+    }
+
+    /// Mutably borrow the Storage along with its change ticks
+    /// Fails with BorrowConflict if it is already borrowed
+    #[track_caller]
+    pub(crate) fn try_borrow_tracked_mut(
+        &self,
+    ) -> Result<RefMut<'_, TrackedStorage<T>>, GerustError> {
+        self.storage
+            .try_borrow_mut_at(&self.borrows, Location::caller())
+            .ok_or_else(|| self.conflict())
+    }
+
+    fn conflict(&self) -> GerustError {
+        GerustError::BorrowConflict {
+            type_name: any::type_name::<T>(),
+            borrowed_at: self.borrows.live(),
+        }
+    }
+}
+
+/// Type erased view of a component Storage
+/// Lets the Engine act on every Storage without knowing its component type
+pub(crate) trait AnyStorage: ThreadSafe {
+    /// Get the Storage as Any so it can be downcast to its concrete type
+    fn as_any(&self) -> &dyn Any;
+
+    /// Remove an entity component if it is present, recording the removal at tick
+    fn remove_entity(&self, index: EntityIndex, tick: Tick);
+
+    /// Forget the removals made before tick
+    fn clear_removed(&self, tick: Tick, change_tick: Tick);
+
+    /// Clamp the ticks of every component, see ComponentTicks::clamp
+    fn clamp_ticks(&self, change_tick: Tick);
+
+    /// Mutably borrow the Storage, to move entities to other archetypes
+    /// Fails with BorrowConflict if it is already borrowed
+    fn try_borrow_mover(&self) -> Result<Box<dyn ArchetypeMover + '_>, GerustError>;
+}
+
+/// A Storage mutably borrowed through AnyStorage
+/// Every Storage of an entity is borrowed before it changes archetype, so a conflict
+/// leaves the entity untouched
+pub(crate) trait ArchetypeMover {
+    /// Forward an archetype change to the Storage
+    fn move_to_archetype(&mut self, index: EntityIndex, archetype: ArchetypeId);
+}
+
+impl<T: Component> ArchetypeMover for RefMut<'_, dyn StorageTrait<T>> {
+    fn move_to_archetype(&mut self, index: EntityIndex, archetype: ArchetypeId) {
+        (**self).move_to_archetype(index, archetype);
+    }
+}
+
+impl<T: Component> AnyStorage for StorageCell<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn remove_entity(&self, index: EntityIndex, tick: Tick) {
+        let mut tracked = self
+            .try_borrow_tracked_mut()
+            .unwrap_or_else(|err| panic!("Could not remove component: {}", err));
+        if tracked.storage.remove_entity(index).is_some() {
+            tracked.removals.push(index, tick);
+        }
+    }
+
+    fn clear_removed(&self, tick: Tick, change_tick: Tick) {
+        self.try_borrow_tracked_mut()
+            .unwrap_or_else(|err| panic!("Could not clear removed components: {}", err))
+            .removals
+            .clear_before(tick, change_tick);
+    }
+
+    fn clamp_ticks(&self, change_tick: Tick) {
+        self.try_borrow_mut()
+            .unwrap_or_else(|err| panic!("Could not clamp component ticks: {}", err))
+            .clamp_ticks(change_tick);
+    }
+
+    fn try_borrow_mover(&self) -> Result<Box<dyn ArchetypeMover + '_>, GerustError> {
+        Ok(Box::new(self.try_borrow_mut()?))
     }
 }
 
+/// Distinct components whose storages can be mutably borrowed at once
+/// Implemented for tuples of components, see Engine::borrow_many
+pub trait ComponentSet {
+    /// Mutably borrowed storages, in the order of the tuple
+    type Storages<'e>;
+
+    /// Mutably borrow every storage of the set
+    /// Fails on the first component that isn't registered or is already borrowed
+    fn try_borrow_many(engine: &Engine) -> Result<Self::Storages<'_>, GerustError>;
+}
+
+macro_rules! impl_component_set {
+    ($($name:ident),+) => {
+        impl<$($name: Component),+> ComponentSet for ($($name,)+) {
+            type Storages<'e> = ($(RefMut<'e, dyn StorageTrait<$name>>,)+);
+
+            #[track_caller]
+            fn try_borrow_many(engine: &Engine) -> Result<Self::Storages<'_>, GerustError> {
+                Ok(($(engine.try_get_component_mut::<$name>()?,)+))
+            }
+        }
+    };
+}
+
+impl_component_set!(A);
+impl_component_set!(A, B);
+impl_component_set!(A, B, C);
+impl_component_set!(A, B, C, D);
+impl_component_set!(A, B, C, D, E);
+impl_component_set!(A, B, C, D, E, F);
+impl_component_set!(A, B, C, D, E, F, G);
+impl_component_set!(A, B, C, D, E, F, G, H);
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -69,14 +379,83 @@ mod test {
         }
     }
 
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    impl Component for Health {}
+
     #[test]
     fn add_entities() {
         let mut storage: Storage<BasicComponent> = Storage::new();
 
-        storage.add_entity(10, BasicComponent::new());
-        storage.add_entity(3, BasicComponent::new());
-        storage.add_entity(5420, BasicComponent::new());
+        storage.add_entity(EntityIndex::new(10, 0), BasicComponent::new());
+        storage.add_entity(EntityIndex::new(3, 0), BasicComponent::new());
+        storage.add_entity(EntityIndex::new(5420, 0), BasicComponent::new());
 
         assert!(storage.entity_components.len() == 3)
     }
+
+    #[test]
+    fn remove_entity() {
+        let mut storage: Storage<BasicComponent> = Storage::new();
+        storage.add_entity(EntityIndex::new(3, 0), BasicComponent::new());
+
+        assert!(storage.remove_entity(EntityIndex::new(3, 0)).is_some());
+        assert!(storage.remove_entity(EntityIndex::new(3, 0)).is_none());
+        assert!(storage.entity_components.is_empty());
+    }
+
+    #[test]
+    fn try_get_missing() {
+        let mut storage: Storage<BasicComponent> = Storage::new();
+        storage.add_entity(EntityIndex::new(3, 0), BasicComponent::new());
+
+        assert!(storage.try_get(EntityIndex::new(3, 0)).is_ok());
+        assert!(matches!(
+            storage.try_get_mut(EntityIndex::new(3, 1)),
+            Err(GerustError::MissingComponent { .. })
+        ));
+    }
+
+    #[test]
+    fn ticks() {
+        let mut storage: Storage<Health> = Storage::new();
+        storage.add_entity_with_ticks(EntityIndex::new(3, 0), Health(3), ComponentTicks::new(5));
+        storage.add_entity(EntityIndex::new(4, 0), Health(4));
+
+        storage.clamp_ticks(20 + crate::MAX_CHANGE_AGE);
+
+        assert!(storage.ticks(EntityIndex::new(3, 0)) == Some(ComponentTicks::new(20)));
+        assert!(storage.ticks(EntityIndex::new(4, 0)) == Some(ComponentTicks::new(20)));
+        assert!(storage.ticks(EntityIndex::new(3, 1)).is_none());
+    }
+
+    #[test]
+    fn iterate() {
+        let mut storage: Storage<Health> = Storage::new();
+        storage.add_entity(EntityIndex::new(3, 0), Health(3));
+        storage.add_entity(EntityIndex::new(5, 0), Health(5));
+
+        for (_, component) in storage.iter_mut() {
+            component.0 *= 2;
+        }
+        let mut components: Vec<_> = storage.iter().map(|(_, component)| component.0).collect();
+        components.sort_unstable();
+
+        assert!(components == [6, 10]);
+        assert!(storage.len() == 2);
+        assert!(storage.contains(EntityIndex::new(5, 0)));
+        assert!(!storage.contains(EntityIndex::new(5, 1)));
+    }
+
+    #[test]
+    fn drain() {
+        let mut storage: Storage<Health> = Storage::new();
+        storage.add_entity(EntityIndex::new(3, 0), Health(3));
+
+        let drained: Vec<_> = storage.drain().collect();
+
+        assert!(drained == [(EntityIndex::new(3, 0), Health(3))]);
+        assert!(storage.is_empty());
+        assert!(storage.entities().next().is_none());
+    }
 }
@@ -1,7 +1,445 @@
-use crate::{Engine, UpdateStatus};
+use crate::tick::clamp_tick;
+use crate::{
+    Access, Engine, GerustError, Stage, SystemParam, SystemTicks, ThreadSafe, Tick, UpdateStatus,
+};
 use sdl2::event::Event;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU32, Ordering};
 
-pub trait System {
+pub trait System: ThreadSafe {
     /// Called on every frame, Returning Ok(UpdateStatus::Exit) exits the engine
-    fn update(&self, engine: &Engine, events: &[Event]) -> Result<UpdateStatus, String>;
+    fn update(&self, engine: &Engine, events: &[Event]) -> Result<UpdateStatus, GerustError>;
+
+    /// Name of the system, used to report its errors
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Components and resources accessed by the system
+    /// Defaults to the whole Engine, override it to let the Engine know better
+    fn access(&self) -> Access {
+        Access::exclusive()
+    }
+
+    /// Clamp the ticks kept by the system, see ComponentTicks::clamp
+    /// The Engine calls it every so often, so that ticks can wrap around
+    fn clamp_change_ticks(&self, _change_tick: Tick) {}
+}
+
+/// What a function system can return
+/// Implemented for (), UpdateStatus and Results of those
+pub trait SystemOutput {
+    fn into_status(self) -> Result<UpdateStatus, GerustError>;
+}
+
+impl SystemOutput for () {
+    fn into_status(self) -> Result<UpdateStatus, GerustError> {
+        Ok(UpdateStatus::Continue)
+    }
+}
+
+impl SystemOutput for UpdateStatus {
+    fn into_status(self) -> Result<UpdateStatus, GerustError> {
+        Ok(self)
+    }
+}
+
+impl SystemOutput for Result<(), GerustError> {
+    fn into_status(self) -> Result<UpdateStatus, GerustError> {
+        self.map(|_| UpdateStatus::Continue)
+    }
+}
+
+impl SystemOutput for Result<UpdateStatus, GerustError> {
+    fn into_status(self) -> Result<UpdateStatus, GerustError> {
+        self
+    }
+}
+
+/// A function or closure whose parameters are all SystemParams
+/// Marker is the signature of the function, it only tells implementations apart
+pub trait SystemParamFunction<Marker>: 'static + ThreadSafe {
+    /// Access of each parameter, in order
+    fn param_accesses() -> Vec<Access>;
+
+    /// Fetch the parameters from the Engine and call the function
+    fn run(&self, engine: &Engine, ticks: SystemTicks) -> Result<UpdateStatus, GerustError>;
+}
+
+macro_rules! impl_system_param_function {
+    ($($param:ident),*) => {
+        impl<Func, Out, $($param: SystemParam),*> SystemParamFunction<fn($($param,)*) -> Out>
+            for Func
+        where
+            Func: 'static + ThreadSafe + Fn($($param),*) -> Out + for<'e> Fn($($param::Item<'e>),*) -> Out,
+            Out: SystemOutput,
+        {
+            fn param_accesses() -> Vec<Access> {
+                vec![$({
+                    let mut access = Access::new();
+                    $param::access(&mut access);
+                    access
+                }),*]
+            }
+
+            #[allow(non_snake_case, unused_variables)]
+            fn run(&self, engine: &Engine, ticks: SystemTicks) -> Result<UpdateStatus, GerustError> {
+                // Going through a generic helper makes the call use the Fn(Item) bound
+                #[allow(clippy::too_many_arguments)]
+                fn call<Out, $($param),*>(func: impl Fn($($param),*) -> Out, $($param: $param),*) -> Out {
+                    func($($param),*)
+                }
+                $(let $param = $param::fetch(engine, ticks);)*
+                call(self, $($param),*).into_status()
+            }
+        }
+    };
+}
+
+impl_system_param_function!();
+impl_system_param_function!(A);
+impl_system_param_function!(A, B);
+impl_system_param_function!(A, B, C);
+impl_system_param_function!(A, B, C, D);
+impl_system_param_function!(A, B, C, D, E);
+impl_system_param_function!(A, B, C, D, E, F);
+impl_system_param_function!(A, B, C, D, E, F, G);
+impl_system_param_function!(A, B, C, D, E, F, G, H);
+
+/// A System running a function, created by Engine::register_system
+pub struct FunctionSystem<Func, Marker> {
+    func: Func,
+    access: Access,
+    /// Tick of the previous run, 0 if it never ran
+    last_run: AtomicU32,
+    marker: PhantomData<fn() -> Marker>,
+}
+
+impl<Func: SystemParamFunction<Marker>, Marker> FunctionSystem<Func, Marker> {
+    /// Wrap a function as a System
+    /// Will panic if two parameters access the same data and one of them writes it
+    pub fn new(func: Func) -> FunctionSystem<Func, Marker> {
+        let mut access = Access::new();
+        for param in Func::param_accesses() {
+            let conflicts = access.conflicts(&param);
+            if !conflicts.is_empty() {
+                panic!(
+                    "System {} has conflicting parameters on {}",
+                    std::any::type_name::<Func>(),
+                    conflicts.join(", ")
+                );
+            }
+            access.extend(&param);
+        }
+        FunctionSystem {
+            func,
+            access,
+            last_run: AtomicU32::new(0),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<Func: SystemParamFunction<Marker>, Marker: 'static> System for FunctionSystem<Func, Marker> {
+    fn update(&self, engine: &Engine, _: &[Event]) -> Result<UpdateStatus, GerustError> {
+        let ticks = SystemTicks {
+            last_run: self.last_run.load(Ordering::Relaxed),
+            this_run: engine.next_change_tick(),
+        };
+        let status = self.func.run(engine, ticks);
+        self.last_run.store(ticks.this_run, Ordering::Relaxed);
+        status
+    }
+
+    fn name(&self) -> &str {
+        std::any::type_name::<Func>()
+    }
+
+    fn access(&self) -> Access {
+        self.access.clone()
+    }
+
+    fn clamp_change_ticks(&self, change_tick: Tick) {
+        let mut last_run = self.last_run.load(Ordering::Relaxed);
+        clamp_tick(&mut last_run, change_tick);
+        self.last_run.store(last_run, Ordering::Relaxed);
+    }
+}
+
+/// Anything that can be registered as a System: System implementations,
+/// functions and closures taking SystemParams
+pub trait IntoSystem<Marker> {
+    fn into_system(self) -> Box<dyn System>;
+}
+
+impl<S: 'static + System> IntoSystem<()> for S {
+    fn into_system(self) -> Box<dyn System> {
+        Box::new(self)
+    }
+}
+
+impl<Func: SystemParamFunction<Marker>, Marker: 'static> IntoSystem<(Marker,)> for Func {
+    fn into_system(self) -> Box<dyn System> {
+        Box::new(FunctionSystem::new(self))
+    }
+}
+
+/// A System along with where it runs in the schedule
+/// Created by the methods of IntoSystemDescriptor, e.g. `apply_velocity.after("gravity")`
+pub struct SystemDescriptor {
+    pub(crate) system: Box<dyn System>,
+
+    /// Name used by ordering constraints and to report errors, the System name by default
+    pub(crate) name: String,
+
+    /// None to use the stage of the register function
+    pub(crate) stage: Option<Stage>,
+
+    /// Names of the systems this one must run before
+    pub(crate) before: Vec<String>,
+
+    /// Names of the systems this one must run after
+    pub(crate) after: Vec<String>,
+}
+
+/// Anything that can be registered as a System, optionally placed in the schedule
+/// Systems of a stage run in registration order unless constrained otherwise
+pub trait IntoSystemDescriptor<Marker>: Sized {
+    fn into_descriptor(self) -> SystemDescriptor;
+
+    /// Set the name other systems use to order themselves relative to this one
+    fn named(self, name: impl Into<String>) -> SystemDescriptor {
+        let mut descriptor = self.into_descriptor();
+        descriptor.name = name.into();
+        descriptor
+    }
+
+    /// Run the system in the given stage
+    fn in_stage(self, stage: Stage) -> SystemDescriptor {
+        let mut descriptor = self.into_descriptor();
+        descriptor.stage = Some(stage);
+        descriptor
+    }
+
+    /// Run the system before every system with the given name in its stage
+    fn before(self, name: impl Into<String>) -> SystemDescriptor {
+        let mut descriptor = self.into_descriptor();
+        descriptor.before.push(name.into());
+        descriptor
+    }
+
+    /// Run the system after every system with the given name in its stage
+    fn after(self, name: impl Into<String>) -> SystemDescriptor {
+        let mut descriptor = self.into_descriptor();
+        descriptor.after.push(name.into());
+        descriptor
+    }
+}
+
+impl IntoSystemDescriptor<()> for SystemDescriptor {
+    fn into_descriptor(self) -> SystemDescriptor {
+        self
+    }
+}
+
+impl<S: IntoSystem<Marker>, Marker> IntoSystemDescriptor<(Marker,)> for S {
+    fn into_descriptor(self) -> SystemDescriptor {
+        let system = self.into_system();
+        SystemDescriptor {
+            name: system.name().to_string(),
+            system,
+            stage: None,
+            before: vec![],
+            after: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{new_engine, Velocity};
+    use crate::{
+        Added, Changed, Commands, Component, NonSend, Query, RemovedComponents, Res, ResMut, Time,
+        With,
+    };
+
+    struct Player;
+    impl Component for Player {}
+
+    #[derive(Default)]
+    struct Steps(u32);
+
+    fn accelerate(mut velocities: Query<&mut Velocity>, time: Res<Time>) {
+        for (_, mut velocity) in velocities.iter_mut() {
+            velocity.0 += time.tick_count() as i32;
+        }
+    }
+
+    fn player_engine() -> (Engine, crate::EntityIndex) {
+        let mut engine = new_engine();
+        engine.register_component::<Player>();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Velocity(0));
+        (engine, entity)
+    }
+
+    #[test]
+    fn function_system() {
+        let (mut engine, entity) = player_engine();
+        engine.register_system(accelerate);
+
+        engine.step_n(2).unwrap();
+
+        // Time is advanced before the systems run, so ticks are 1 then 2
+        assert!(engine.get_component_ref::<Velocity>().get(entity).0 == 3);
+    }
+
+    #[test]
+    fn closure_system() {
+        let (mut engine, _) = player_engine();
+        engine.insert_resource(Steps::default());
+        engine.register_system(|mut steps: ResMut<Steps>, commands: Commands| {
+            steps.0 += 1;
+            let entity = commands.spawn();
+            commands.insert(entity, Player);
+            if steps.0 == 3 {
+                UpdateStatus::Exit
+            } else {
+                UpdateStatus::Continue
+            }
+        });
+
+        assert!(matches!(engine.step_n(10), Ok(UpdateStatus::Exit)));
+        assert!(engine.resource::<Steps>().0 == 3);
+        assert!(engine
+            .query_filtered::<&Velocity, With<Player>>()
+            .is_empty());
+        assert!(engine.query::<&Player>().len() == 3);
+    }
+
+    #[test]
+    fn function_system_error() {
+        let (mut engine, _) = player_engine();
+        engine.register_system(|| -> Result<(), GerustError> { Err("no".into()) });
+
+        assert!(matches!(
+            engine.step(&[]),
+            Err(GerustError::SystemFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn optional_non_send_system() {
+        let (mut engine, _) = player_engine();
+        engine.insert_resource(Steps::default());
+        engine.register_system(|steps: Option<NonSend<u32>>, mut seen: ResMut<Steps>| {
+            seen.0 += steps.map_or(1, |steps| *steps);
+        });
+        let access = (|_: Option<NonSend<u32>>| {})
+            .into_system()
+            .access()
+            .clone();
+
+        engine.step(&[]).unwrap();
+        engine.insert_non_send_resource(10_u32);
+        engine.step(&[]).unwrap();
+
+        assert!(engine.resource::<Steps>().0 == 11);
+        assert!(access.has_resource::<u32>() && !access.has_resource_write::<u32>());
+        assert!(access.is_main_thread());
+    }
+
+    #[test]
+    fn function_system_access() {
+        let system = (|_: Query<(&mut Velocity, &Player)>, _: Res<Time>| {}).into_system();
+        let access = system.access();
+
+        assert!(access.has_component_write::<Velocity>());
+        assert!(access.has_component::<Player>() && !access.has_component_write::<Player>());
+        assert!(access.has_resource::<Time>() && !access.has_resource_write::<Time>());
+        assert!(!access.is_exclusive());
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicting parameters on gerust::test_utils::Velocity")]
+    fn conflicting_parameters() {
+        let (mut engine, _) = player_engine();
+
+        engine.register_system(|_: Query<&Velocity>, _: Query<&mut Velocity>| {});
+    }
+
+    #[derive(Default)]
+    struct Seen {
+        added: usize,
+        changed: usize,
+        removed: usize,
+    }
+
+    #[test]
+    fn change_detection() {
+        let (mut engine, entity) = player_engine();
+        engine.insert_resource(Seen::default());
+        engine.register_system(
+            |added: Query<&Velocity, Added<Velocity>>,
+             changed: Query<&Velocity, Changed<Velocity>>,
+             removed: RemovedComponents<Velocity>,
+             mut seen: ResMut<Seen>| {
+                seen.added += added.len();
+                seen.changed += changed.len();
+                seen.removed += removed.len();
+            },
+        );
+
+        engine.step_n(2).unwrap();
+        for (_, mut velocity) in engine.query::<&mut Velocity>().iter_mut() {
+            velocity.0 += 1;
+        }
+        engine.step_n(2).unwrap();
+        engine.remove_entity_component::<Velocity>(entity);
+        engine.step_n(2).unwrap();
+
+        let seen = engine.resource::<Seen>();
+        assert!(seen.added == 1);
+        assert!(seen.changed == 2);
+        assert!(seen.removed == 1);
+    }
+
+    #[test]
+    fn changes_of_a_system() {
+        let (mut engine, _) = player_engine();
+        engine.insert_resource(Seen::default());
+        engine.register_system(
+            |changed: Query<&Velocity, Changed<Velocity>>, mut seen: ResMut<Seen>| {
+                seen.changed += changed.len();
+            },
+        );
+        // Registered after the observer, its changes are seen on the next step
+        engine.register_system(accelerate);
+
+        engine.step_n(3).unwrap();
+
+        assert!(engine.resource::<Seen>().changed == 3);
+    }
+
+    #[test]
+    fn changes_across_tick_wrap() {
+        let mut engine = Engine::headless();
+        engine.register_component::<Velocity>();
+        *engine.change_tick.get_mut() = u32::MAX - 2;
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Velocity(0));
+        engine.insert_resource(Seen::default());
+        engine.register_system(
+            |changed: Query<&Velocity, Changed<Velocity>>, mut seen: ResMut<Seen>| {
+                seen.changed += changed.len();
+            },
+        );
+        engine.register_system(accelerate);
+
+        // The last step sees a change made at tick 0 by a system which last ran at u32::MAX
+        engine.step_n(3).unwrap();
+
+        assert!(engine.resource::<Seen>().changed == 3);
+    }
 }
@@ -0,0 +1,256 @@
+use crate::cell::{Ref, RefMut};
+use crate::{
+    Access, Commands, Component, Engine, EntityIndex, Fetch, Query, QueryFilter, SystemTicks,
+};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// Data a function system can take as parameter
+/// e.g. `fn gravity(velocities: Query<&mut Velocity>, time: Res<Time>)`
+pub trait SystemParam {
+    /// The parameter handed to the system, borrowing the Engine
+    type Item<'e>;
+
+    /// Add the data read and written by the parameter to access
+    fn access(access: &mut Access);
+
+    /// Get the parameter from the Engine for a system run with the given ticks
+    /// Will panic if the data is missing or already borrowed
+    fn fetch(engine: &Engine, ticks: SystemTicks) -> Self::Item<'_>;
+}
+
+/// Read access to a global resource
+pub struct Res<'e, R: 'static>(Ref<'e, R>);
+
+/// Write access to a global resource
+pub struct ResMut<'e, R: 'static>(RefMut<'e, R>);
+
+impl<'e, R: 'static> Deref for Res<'e, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        &self.0
+    }
+}
+
+impl<'e, R: 'static> Deref for ResMut<'e, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        &self.0
+    }
+}
+
+impl<'e, R: 'static> DerefMut for ResMut<'e, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        &mut self.0
+    }
+}
+
+/// Entities which lost their component T, by removal or despawn, since the system last ran
+/// Removals are kept for two fixed updates, so render systems may miss some
+pub struct RemovedComponents<'e, T: Component> {
+    removed: Vec<EntityIndex>,
+    marker: PhantomData<&'e T>,
+}
+
+impl<'e, T: Component> RemovedComponents<'e, T> {
+    /// Iterate over the entities which lost the component, in removal order
+    pub fn iter(&self) -> std::slice::Iter<'_, EntityIndex> {
+        self.removed.iter()
+    }
+
+    /// Get the number of removals
+    pub fn len(&self) -> usize {
+        self.removed.len()
+    }
+
+    /// Check if no component was removed
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+    }
+}
+
+impl<'r, 'e, T: Component> IntoIterator for &'r RemovedComponents<'e, T> {
+    type Item = &'r EntityIndex;
+    type IntoIter = std::slice::Iter<'r, EntityIndex>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Read access to a resource that can't leave the main thread, such as the Canvas
+/// Systems taking one always run on the main thread
+pub struct NonSend<'e, R: 'static>(Ref<'e, R>);
+
+/// Write access to a resource that can't leave the main thread
+pub struct NonSendMut<'e, R: 'static>(RefMut<'e, R>);
+
+impl<'e, R: 'static> Deref for NonSend<'e, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        &self.0
+    }
+}
+
+impl<'e, R: 'static> Deref for NonSendMut<'e, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        &self.0
+    }
+}
+
+impl<'e, R: 'static> DerefMut for NonSendMut<'e, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        &mut self.0
+    }
+}
+
+impl<'w, Q: Fetch, F: QueryFilter> SystemParam for Query<'w, Q, F> {
+    type Item<'e> = Query<'e, Q, F>;
+
+    fn access(access: &mut Access) {
+        Q::access(access);
+        F::access(access);
+    }
+
+    #[track_caller]
+    fn fetch(engine: &Engine, ticks: SystemTicks) -> Self::Item<'_> {
+        Query::with_ticks(engine, ticks)
+    }
+}
+
+impl<'w, R: 'static> SystemParam for Res<'w, R> {
+    type Item<'e> = Res<'e, R>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_read::<R>();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        Res(engine.resource())
+    }
+}
+
+impl<'w, R: 'static> SystemParam for ResMut<'w, R> {
+    type Item<'e> = ResMut<'e, R>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_write::<R>();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        ResMut(engine.resource_mut())
+    }
+}
+
+impl<'w, R: 'static> SystemParam for Option<Res<'w, R>> {
+    type Item<'e> = Option<Res<'e, R>>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_read::<R>();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        engine.try_resource().map(Res)
+    }
+}
+
+impl<'w, R: 'static> SystemParam for Option<ResMut<'w, R>> {
+    type Item<'e> = Option<ResMut<'e, R>>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_write::<R>();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        engine.try_resource_mut().map(ResMut)
+    }
+}
+
+impl<'w, R: 'static> SystemParam for NonSend<'w, R> {
+    type Item<'e> = NonSend<'e, R>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_read::<R>();
+        access.set_main_thread();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        NonSend(engine.non_send_resource())
+    }
+}
+
+impl<'w, R: 'static> SystemParam for NonSendMut<'w, R> {
+    type Item<'e> = NonSendMut<'e, R>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_write::<R>();
+        access.set_main_thread();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        NonSendMut(engine.non_send_resource_mut())
+    }
+}
+
+impl<'w, R: 'static> SystemParam for Option<NonSend<'w, R>> {
+    type Item<'e> = Option<NonSend<'e, R>>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_read::<R>();
+        access.set_main_thread();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        engine.try_non_send_resource().map(NonSend)
+    }
+}
+
+impl<'w, R: 'static> SystemParam for Option<NonSendMut<'w, R>> {
+    type Item<'e> = Option<NonSendMut<'e, R>>;
+
+    fn access(access: &mut Access) {
+        access.add_resource_write::<R>();
+        access.set_main_thread();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        engine.try_non_send_resource_mut().map(NonSendMut)
+    }
+}
+
+impl<'w, T: Component> SystemParam for RemovedComponents<'w, T> {
+    type Item<'e> = RemovedComponents<'e, T>;
+
+    fn access(access: &mut Access) {
+        access.add_component_read::<T>();
+    }
+
+    #[track_caller]
+    fn fetch(engine: &Engine, ticks: SystemTicks) -> Self::Item<'_> {
+        let tracked = engine
+            .try_get_tracked::<T>()
+            .unwrap_or_else(|err| panic!("Could not get component: {}", err));
+        RemovedComponents {
+            removed: tracked.removals.since(ticks).collect(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Commands are applied after the system, so they don't access anything while it runs
+impl<'w> SystemParam for Commands<'w> {
+    type Item<'e> = Commands<'e>;
+
+    fn access(access: &mut Access) {
+        access.set_commands();
+    }
+
+    fn fetch(engine: &Engine, _: SystemTicks) -> Self::Item<'_> {
+        engine.commands()
+    }
+}
@@ -0,0 +1,17 @@
+use crate::{Component, Engine};
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Position(pub i32);
+impl Component for Position {}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Velocity(pub i32);
+impl Component for Velocity {}
+
+/// Headless Engine with Position and Velocity registered, shared by the unit tests
+pub(crate) fn new_engine() -> Engine {
+    let mut engine = Engine::headless();
+    engine.register_component::<Position>();
+    engine.register_component::<Velocity>();
+    engine
+}
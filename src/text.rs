@@ -0,0 +1,370 @@
+use crate::{
+    Camera2D, Component, GerustError, GlobalTransform2D, NonSendMut, Query, TextureHandle,
+    Textures, Vec2,
+};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::ttf::{Font, Sdl2TtfContext};
+use sdl2::video::Window;
+use std::collections::HashMap;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+/// Handle to a font file loaded in the Fonts resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FontHandle(usize);
+
+/// Point size fonts are first opened at, to check that they can be loaded
+const DEFAULT_SIZE: u16 = 16;
+
+/// A character rendered to a texture, white so that any color can be applied when drawing it
+#[derive(Clone, Copy, Debug)]
+struct Glyph {
+    /// None for glyphs without pixels, such as spaces
+    texture: Option<TextureHandle>,
+    /// Distance to the next glyph, in pixels
+    advance: i32,
+}
+
+/// Glyphs rendered so far, for each font, size and character
+#[derive(Default)]
+struct GlyphCache(HashMap<(FontHandle, u16, char), Glyph>);
+
+impl GlyphCache {
+    /// Get a glyph, rendering it only the first time it is asked for
+    fn get_or_render(
+        &mut self,
+        key: (FontHandle, u16, char),
+        render: impl FnOnce() -> Result<Glyph, GerustError>,
+    ) -> Result<Glyph, GerustError> {
+        if let Some(&glyph) = self.0.get(&key) {
+            return Ok(glyph);
+        }
+        let glyph = render()?;
+        self.0.insert(key, glyph);
+        Ok(glyph)
+    }
+}
+
+/// TTF fonts used by Text, a non-send resource of every windowed Engine with the ttf feature
+/// Each glyph is rendered once per font and size, then drawn from its texture
+/// SDL2_ttf is only initialized once the first font is loaded
+#[derive(Default)]
+pub struct Fonts {
+    paths: Vec<PathBuf>,
+    handles: HashMap<PathBuf, FontHandle>,
+    glyphs: GlyphCache,
+    /// Each font file opened at each size Text asked for, declared before context so that
+    /// fonts are closed before SDL2_ttf is
+    opened: HashMap<(FontHandle, u16), Font<'static, 'static>>,
+    context: Option<Sdl2TtfContext>,
+}
+
+impl Fonts {
+    /// Load a TTF or OTF font file, or get the one already loaded from path
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<FontHandle, GerustError> {
+        let path = path.as_ref();
+        if let Some(&handle) = self.handles.get(path) {
+            return Ok(handle);
+        }
+        let handle = FontHandle(self.paths.len());
+        let font = self.open(path, DEFAULT_SIZE)?;
+        self.paths.push(path.to_path_buf());
+        self.handles.insert(path.to_path_buf(), handle);
+        self.opened.insert((handle, DEFAULT_SIZE), font);
+        Ok(handle)
+    }
+
+    fn open(&mut self, path: &Path, size: u16) -> Result<Font<'static, 'static>, GerustError> {
+        if self.context.is_none() {
+            let context = sdl2::ttf::init().map_err(|e| GerustError::Sdl(e.to_string()))?;
+            self.context = Some(context);
+        }
+        let context = self.context.as_ref().unwrap();
+        let font = context.load_font(path, size).map_err(GerustError::Sdl)?;
+        // Safety: the font is only kept in self.opened, which is dropped before self.context
+        // and never lends a font for longer than self
+        Ok(unsafe { mem::transmute::<Font<'_, 'static>, Font<'static, 'static>>(font) })
+    }
+
+    fn font(&mut self, handle: FontHandle, size: u16) -> Result<&Font<'_, 'static>, GerustError> {
+        if !self.opened.contains_key(&(handle, size)) {
+            let path = self
+                .paths
+                .get(handle.0)
+                .cloned()
+                .ok_or_else(|| GerustError::Sdl(format!("No font loaded for {:?}", handle)))?;
+            let font = self.open(&path, size)?;
+            self.opened.insert((handle, size), font);
+        }
+        Ok(&self.opened[&(handle, size)])
+    }
+
+    /// Distance between two lines of text, in pixels
+    fn line_height(&mut self, handle: FontHandle, size: u16) -> Result<i32, GerustError> {
+        Ok(self.font(handle, size)?.recommended_line_spacing())
+    }
+
+    fn glyph(
+        &mut self,
+        textures: &mut Textures,
+        handle: FontHandle,
+        size: u16,
+        ch: char,
+    ) -> Result<Glyph, GerustError> {
+        let mut glyphs = mem::take(&mut self.glyphs);
+        let glyph = glyphs.get_or_render((handle, size, ch), || {
+            let font = self.font(handle, size)?;
+            let advance = font
+                .find_glyph_metrics(ch)
+                .map_or(0, |metrics| metrics.advance);
+            let texture = if ch.is_whitespace() {
+                None
+            } else {
+                let surface = font
+                    .render_char(ch)
+                    .blended(Color::WHITE)
+                    .map_err(|e| GerustError::Sdl(e.to_string()))?;
+                Some(textures.add_surface(&surface)?)
+            };
+            Ok(Glyph { texture, advance })
+        });
+        self.glyphs = glyphs;
+        glyph
+    }
+}
+
+/// How the lines of a Text are placed relative to its anchor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alignment {
+    /// Lines start at the anchor
+    #[default]
+    Left,
+    /// Lines are centered on the anchor
+    Center,
+    /// Lines end at the anchor
+    Right,
+}
+
+/// String drawn by render_text, with its first line at the translation of its GlobalTransform2D
+/// Text stays upright and is only scaled by the zoom of the cameras
+#[derive(Clone, Debug, PartialEq)]
+pub struct Text {
+    pub value: String,
+    pub font: FontHandle,
+    /// Point size the font is rendered at
+    pub size: u16,
+    pub color: Color,
+    pub alignment: Alignment,
+    /// Drawn at screen pixels instead of through every Camera2D, e.g. for scores and menus
+    pub screen_space: bool,
+}
+
+impl Text {
+    pub fn new(value: impl Into<String>, font: FontHandle, size: u16) -> Text {
+        Text {
+            value: value.into(),
+            font,
+            size,
+            color: Color::WHITE,
+            alignment: Alignment::Left,
+            screen_space: false,
+        }
+    }
+
+    pub fn with_color(self, color: Color) -> Text {
+        Text { color, ..self }
+    }
+
+    pub fn with_alignment(self, alignment: Alignment) -> Text {
+        Text { alignment, ..self }
+    }
+
+    /// Draw the text at screen pixels instead of through every Camera2D
+    pub fn in_screen_space(self) -> Text {
+        Text {
+            screen_space: true,
+            ..self
+        }
+    }
+}
+
+impl Component for Text {}
+
+/// Offset of each line from the anchor, given their widths
+fn line_offsets(widths: &[i32], line_height: i32, alignment: Alignment) -> Vec<(i32, i32)> {
+    widths
+        .iter()
+        .enumerate()
+        .map(|(line, &width)| {
+            let x = match alignment {
+                Alignment::Left => 0,
+                Alignment::Center => -width / 2,
+                Alignment::Right => -width,
+            };
+            (x, line as i32 * line_height)
+        })
+        .collect()
+}
+
+/// Draw every Text with a GlobalTransform2D through every Camera2D, then the screen space ones
+/// Registered in Stage::Render by Engine::new, after "render_sprites"
+pub fn render_text(
+    mut canvas: NonSendMut<Canvas<Window>>,
+    mut textures: NonSendMut<Textures>,
+    mut fonts: NonSendMut<Fonts>,
+    cameras: Query<&Camera2D>,
+    texts: Query<(&Text, &GlobalTransform2D)>,
+) -> Result<(), GerustError> {
+    let mut cameras: Vec<_> = cameras.iter().map(|(_, camera)| camera).collect();
+    cameras.sort_by_key(|camera| camera.order);
+    for camera in cameras {
+        canvas.set_clip_rect(camera.viewport);
+        for (_, (text, transform)) in texts.iter().filter(|(_, (text, _))| !text.screen_space) {
+            let anchor = camera.world_to_screen(transform.translation);
+            draw_text(
+                &mut canvas,
+                &mut textures,
+                &mut fonts,
+                text,
+                anchor,
+                camera.zoom,
+            )?;
+        }
+    }
+    canvas.set_clip_rect(None);
+
+    for (_, (text, transform)) in texts.iter().filter(|(_, (text, _))| text.screen_space) {
+        draw_text(
+            &mut canvas,
+            &mut textures,
+            &mut fonts,
+            text,
+            transform.translation,
+            1.,
+        )?;
+    }
+    Ok(())
+}
+
+fn draw_text(
+    canvas: &mut Canvas<Window>,
+    textures: &mut Textures,
+    fonts: &mut Fonts,
+    text: &Text,
+    anchor: Vec2,
+    scale: f32,
+) -> Result<(), GerustError> {
+    let mut lines = vec![];
+    for line in text.value.lines() {
+        let glyphs = line
+            .chars()
+            .map(|ch| fonts.glyph(textures, text.font, text.size, ch))
+            .collect::<Result<Vec<_>, _>>()?;
+        lines.push(glyphs);
+    }
+    let widths: Vec<i32> = lines
+        .iter()
+        .map(|glyphs| glyphs.iter().map(|glyph| glyph.advance).sum())
+        .collect();
+    let line_height = fonts.line_height(text.font, text.size)?;
+
+    for (glyphs, (x, y)) in lines
+        .iter()
+        .zip(line_offsets(&widths, line_height, text.alignment))
+    {
+        let mut pen = x;
+        for glyph in glyphs {
+            if let Some(handle) = glyph.texture {
+                let (width, height) = textures.size(handle).unwrap_or((0, 0));
+                let position = anchor + Vec2::new(pen as f32, y as f32) * scale;
+                let destination = Rect::new(
+                    position.x.round() as i32,
+                    position.y.round() as i32,
+                    (width as f32 * scale).round() as u32,
+                    (height as f32 * scale).round() as u32,
+                );
+                textures
+                    .with_mut(handle, |texture| {
+                        texture.set_color_mod(text.color.r, text.color.g, text.color.b);
+                        texture.set_alpha_mod(text.color.a);
+                        canvas.copy(texture, None, destination)
+                    })
+                    .unwrap_or(Ok(()))
+                    .map_err(GerustError::Sdl)?;
+            }
+            pen += glyph.advance;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn glyph(advance: i32) -> Result<Glyph, GerustError> {
+        Ok(Glyph {
+            texture: None,
+            advance,
+        })
+    }
+
+    #[test]
+    fn glyphs_rendered_once() {
+        let mut glyphs = GlyphCache::default();
+        let mut rendered = 0;
+        let key = (FontHandle(0), DEFAULT_SIZE, 'a');
+
+        for _ in 0..3 {
+            let glyph = glyphs.get_or_render(key, || {
+                rendered += 1;
+                glyph(8)
+            });
+            assert!(glyph.unwrap().advance == 8);
+        }
+
+        assert!(rendered == 1);
+    }
+
+    #[test]
+    fn glyphs_of_each_size() {
+        let mut glyphs = GlyphCache::default();
+        let font = FontHandle(0);
+
+        glyphs.get_or_render((font, 16, 'a'), || glyph(8)).unwrap();
+        glyphs.get_or_render((font, 32, 'a'), || glyph(16)).unwrap();
+        let failed = glyphs.get_or_render((font, 48, 'a'), || Err("no font".into()));
+
+        assert!(failed.is_err());
+        let small = glyphs.get_or_render((font, 16, 'a'), || glyph(0)).unwrap();
+        let large = glyphs.get_or_render((font, 32, 'a'), || glyph(0)).unwrap();
+        assert!(small.advance == 8 && large.advance == 16);
+        assert!(
+            glyphs
+                .get_or_render((font, 48, 'a'), || glyph(24))
+                .unwrap()
+                .advance
+                == 24
+        );
+    }
+
+    #[test]
+    fn aligned_lines() {
+        let widths = [40, 20];
+
+        assert!(line_offsets(&widths, 10, Alignment::Left) == [(0, 0), (0, 10)]);
+        assert!(line_offsets(&widths, 10, Alignment::Center) == [(-20, 0), (-10, 10)]);
+        assert!(line_offsets(&widths, 10, Alignment::Right) == [(-40, 0), (-20, 10)]);
+    }
+
+    #[test]
+    fn headless_text() {
+        let engine = crate::Engine::headless();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Text::new("Score", FontHandle(0), 12));
+
+        assert!(engine.query::<&Text>().entities() == [entity]);
+    }
+}
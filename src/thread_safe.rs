@@ -0,0 +1,66 @@
+use sdl2::event::Event;
+use std::thread::{self, ThreadId};
+
+/// Send + Sync when the parallel feature is enabled, implemented by every type otherwise
+/// Bounds data the Engine may share with the worker threads running systems
+#[cfg(feature = "parallel")]
+pub trait ThreadSafe: Send + Sync {}
+
+#[cfg(feature = "parallel")]
+impl<T: Send + Sync> ThreadSafe for T {}
+
+/// Send + Sync when the parallel feature is enabled, implemented by every type otherwise
+/// Bounds data the Engine may share with the worker threads running systems
+#[cfg(not(feature = "parallel"))]
+pub trait ThreadSafe {}
+
+#[cfg(not(feature = "parallel"))]
+impl<T> ThreadSafe for T {}
+
+/// Data that may only be used from the thread that created it, such as SDL handles
+/// Sharing it is fine as long as every access checks the thread
+pub(crate) struct MainThread<T> {
+    thread: ThreadId,
+    value: T,
+}
+
+// Every shared access goes through get, which checks the thread
+unsafe impl<T> Sync for MainThread<T> {}
+
+impl<T> MainThread<T> {
+    pub(crate) fn new(value: T) -> MainThread<T> {
+        MainThread {
+            thread: thread::current().id(),
+            value,
+        }
+    }
+
+    /// Will panic if called from another thread than the one that created self
+    pub(crate) fn get(&self) -> &T {
+        assert!(
+            thread::current().id() == self.thread,
+            "{} can only be used from the main thread",
+            std::any::type_name::<T>()
+        );
+        &self.value
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// SDL events handed to the systems, which may run on other threads
+/// An Event only holds raw pointers in Event::User, as opaque user data the Engine never
+/// reads, so events are safe to move and share between threads
+#[derive(Default)]
+pub(crate) struct SdlEvents<E>(pub(crate) E);
+
+// Owned events are moved along with the resource holding them
+unsafe impl Send for SdlEvents<Vec<Event>> {}
+
+// Owned events are only read through shared references to the resource holding them
+unsafe impl Sync for SdlEvents<Vec<Event>> {}
+
+// Borrowed events are only read by the systems of a batch, which end before the borrow
+unsafe impl Sync for SdlEvents<&[Event]> {}
@@ -0,0 +1,173 @@
+use crate::EntityIndex;
+
+/// Counter of system runs, used to date component changes
+/// Every function system run gets its own tick, ticks wrap around after u32::MAX
+pub type Tick = u32;
+
+/// Number of ticks between two clamps of the ticks kept by components and systems
+pub(crate) const CHECK_TICK_THRESHOLD: Tick = 518_400_000;
+
+/// How old a tick can get before it is clamped, leaving room for the ticks given
+/// between two clamps so that wrapped ticks still compare correctly
+pub const MAX_CHANGE_AGE: Tick = u32::MAX - (2 * CHECK_TICK_THRESHOLD - 1);
+
+/// Bring a tick older than MAX_CHANGE_AGE back to MAX_CHANGE_AGE before change_tick
+pub(crate) fn clamp_tick(tick: &mut Tick, change_tick: Tick) {
+    if change_tick.wrapping_sub(*tick) > MAX_CHANGE_AGE {
+        *tick = change_tick.wrapping_sub(MAX_CHANGE_AGE);
+    }
+}
+
+/// When a component was added and last changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComponentTicks {
+    added: Tick,
+    changed: Tick,
+}
+
+impl ComponentTicks {
+    /// Ticks of a component added at tick
+    pub fn new(tick: Tick) -> ComponentTicks {
+        ComponentTicks {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    /// Tick at which the component was added
+    pub fn added(&self) -> Tick {
+        self.added
+    }
+
+    /// Tick at which the component was last added, replaced or mutably accessed
+    pub fn changed(&self) -> Tick {
+        self.changed
+    }
+
+    /// Check if the component was added after ticks.last_run
+    pub fn is_added(&self, ticks: SystemTicks) -> bool {
+        ticks.is_newer(self.added)
+    }
+
+    /// Check if the component was changed after ticks.last_run
+    pub fn is_changed(&self, ticks: SystemTicks) -> bool {
+        ticks.is_newer(self.changed)
+    }
+
+    pub(crate) fn set_changed(&mut self, tick: Tick) {
+        self.changed = tick;
+    }
+
+    /// Clamp ticks older than MAX_CHANGE_AGE, storages do it when the Engine asks them to
+    pub fn clamp(&mut self, change_tick: Tick) {
+        clamp_tick(&mut self.added, change_tick);
+        clamp_tick(&mut self.changed, change_tick);
+    }
+}
+
+/// Ticks of a running system, or of code running outside of any system
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SystemTicks {
+    /// Tick of the previous run of the system, 0 if it never ran
+    pub last_run: Tick,
+    /// Tick of the current run, given to the components it changes
+    pub this_run: Tick,
+}
+
+impl SystemTicks {
+    /// Check if tick is after last_run
+    /// Ticks are compared by how long before this_run they are, so that they can wrap around
+    pub fn is_newer(&self, tick: Tick) -> bool {
+        self.this_run.wrapping_sub(tick) < self.this_run.wrapping_sub(self.last_run)
+    }
+}
+
+/// Entities which lost a component, with the tick of the removal
+/// Kept next to the Storage of the component
+#[derive(Default)]
+pub(crate) struct Removals(Vec<(EntityIndex, Tick)>);
+
+impl Removals {
+    /// Record that an entity lost the component
+    pub(crate) fn push(&mut self, index: EntityIndex, tick: Tick) {
+        self.0.push((index, tick));
+    }
+
+    /// Entities which lost the component after ticks.last_run
+    pub(crate) fn since(&self, ticks: SystemTicks) -> impl Iterator<Item = EntityIndex> + '_ {
+        self.0
+            .iter()
+            .filter(move |(_, tick)| ticks.is_newer(*tick))
+            .map(|(index, _)| *index)
+    }
+
+    /// Forget the removals made before tick
+    pub(crate) fn clear_before(&mut self, tick: Tick, change_tick: Tick) {
+        let max_age = change_tick.wrapping_sub(tick);
+        self.0
+            .retain(|(_, removed)| change_tick.wrapping_sub(*removed) <= max_age);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn added_and_changed() {
+        let mut component = ComponentTicks::new(2);
+        component.set_changed(4);
+        let ticks = |last_run| SystemTicks {
+            last_run,
+            this_run: 5,
+        };
+
+        assert!(component.added() == 2 && component.changed() == 4);
+        assert!(component.is_added(ticks(1)) && !component.is_added(ticks(2)));
+        assert!(component.is_changed(ticks(3)) && !component.is_changed(ticks(4)));
+    }
+
+    #[test]
+    fn wrapped_ticks() {
+        let component = ComponentTicks::new(1);
+        let ticks = SystemTicks {
+            last_run: u32::MAX - 1,
+            this_run: 3,
+        };
+
+        assert!(component.is_added(ticks));
+        assert!(!ComponentTicks::new(u32::MAX - 2).is_changed(ticks));
+    }
+
+    #[test]
+    fn clamp_old_ticks() {
+        let mut component = ComponentTicks::new(5);
+        let change_tick = 5 + MAX_CHANGE_AGE + 10;
+
+        component.clamp(change_tick);
+
+        assert!(component.added() == 15);
+        assert!(component.is_added(SystemTicks {
+            last_run: 10,
+            this_run: change_tick,
+        }));
+    }
+
+    #[test]
+    fn removed() {
+        let mut removals = Removals::default();
+        let entity1 = EntityIndex::new(0, 0);
+        let entity2 = EntityIndex::new(1, 0);
+
+        removals.push(entity1, 2);
+        removals.push(entity2, 5);
+        removals.clear_before(3, 6);
+
+        let ticks = |last_run| SystemTicks {
+            last_run,
+            this_run: 6,
+        };
+        assert!(removals.since(ticks(0)).collect::<Vec<_>>() == [entity2]);
+        assert!(removals.since(ticks(5)).next().is_none());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::GerustError;
+use std::time::Duration;
+
+/// Timing information available to systems
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Time {
+    delta: Duration,
+    elapsed: Duration,
+    tick_count: u64,
+    frame_count: u64,
+    alpha: f64,
+}
+
+impl Time {
+    /// Time covered by the current update
+    /// The fixed timestep for update systems, the real frame time for render systems
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// Same as delta, in seconds
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Total simulated time since the Engine started
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Number of fixed updates run so far
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// Number of frames rendered so far
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// How far the current frame is between the last fixed update and the next one, in [0, 1)
+    /// Render systems use it to interpolate between simulation states
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Account for a fixed update of the given duration
+    pub(crate) fn advance_tick(&mut self, delta: Duration) {
+        self.delta = delta;
+        self.elapsed += delta;
+        self.tick_count += 1;
+    }
+
+    /// Account for a rendered frame
+    pub(crate) fn advance_frame(&mut self, delta: Duration, alpha: f64) {
+        self.delta = delta;
+        self.alpha = alpha;
+        self.frame_count += 1;
+    }
+}
+
+/// Configuration of the game loop run by Engine::run
+#[derive(Clone, Copy, Debug)]
+pub struct LoopConfig {
+    /// Number of fixed updates per second
+    pub update_rate: f64,
+
+    /// Maximum number of frames rendered per second, None to render as fast as possible
+    pub frame_rate_limit: Option<f64>,
+
+    /// Maximum number of fixed updates run in a single frame to catch up on lag
+    /// Prevents a slow frame from snowballing into ever slower frames
+    pub max_catch_up_steps: u32,
+}
+
+impl LoopConfig {
+    /// Duration of a single fixed update
+    /// Will panic if update_rate is not a positive number, see validate
+    pub fn timestep(&self) -> Duration {
+        Duration::from_secs_f64(1. / self.update_rate)
+    }
+
+    /// Check that every fixed update can run, and that rates can be turned into durations
+    pub fn validate(&self) -> Result<(), GerustError> {
+        // Rates are turned into the duration of one step, which must be representable
+        let period = |rate: f64| {
+            Duration::try_from_secs_f64(1. / rate)
+                .ok()
+                .filter(|period| !period.is_zero())
+        };
+        let timestep = period(self.update_rate).ok_or(GerustError::InvalidLoopConfig {
+            field: "update_rate",
+            reason: "must be a positive number",
+        })?;
+        if self
+            .frame_rate_limit
+            .is_some_and(|limit| period(limit).is_none())
+        {
+            return Err(GerustError::InvalidLoopConfig {
+                field: "frame_rate_limit",
+                reason: "must be a positive number",
+            });
+        }
+        if self.max_catch_up_steps == 0 {
+            return Err(GerustError::InvalidLoopConfig {
+                field: "max_catch_up_steps",
+                reason: "must be at least 1, or fixed updates would never run",
+            });
+        }
+        if timestep.checked_mul(self.max_catch_up_steps).is_none() {
+            return Err(GerustError::InvalidLoopConfig {
+                field: "max_catch_up_steps",
+                reason: "is too large for the update rate",
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for LoopConfig {
+    fn default() -> Self {
+        LoopConfig {
+            update_rate: 60.,
+            frame_rate_limit: Some(60.),
+            max_catch_up_steps: 5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn advance_tick() {
+        let mut time = Time::default();
+
+        time.advance_tick(Duration::from_millis(10));
+        time.advance_tick(Duration::from_millis(10));
+
+        assert!(time.delta() == Duration::from_millis(10));
+        assert!(time.elapsed() == Duration::from_millis(20));
+        assert!(time.tick_count() == 2);
+        assert!(time.frame_count() == 0);
+    }
+
+    #[test]
+    fn advance_frame() {
+        let mut time = Time::default();
+        time.advance_tick(Duration::from_millis(10));
+
+        time.advance_frame(Duration::from_millis(4), 0.5);
+
+        assert!(time.delta() == Duration::from_millis(4));
+        assert!(time.elapsed() == Duration::from_millis(10));
+        assert!(time.frame_count() == 1);
+        assert!(time.alpha() == 0.5);
+    }
+
+    #[test]
+    fn timestep() {
+        let config = LoopConfig {
+            update_rate: 50.,
+            ..LoopConfig::default()
+        };
+
+        assert!(config.timestep() == Duration::from_millis(20));
+    }
+
+    #[test]
+    fn invalid_config() {
+        let invalid_field = |config: LoopConfig| match config.validate() {
+            Err(GerustError::InvalidLoopConfig { field, .. }) => field,
+            _ => "",
+        };
+
+        assert!(LoopConfig::default().validate().is_ok());
+        let config = LoopConfig {
+            update_rate: 0.,
+            ..LoopConfig::default()
+        };
+        assert!(invalid_field(config) == "update_rate");
+        let config = LoopConfig {
+            frame_rate_limit: Some(f64::NAN),
+            ..LoopConfig::default()
+        };
+        assert!(invalid_field(config) == "frame_rate_limit");
+        let config = LoopConfig {
+            max_catch_up_steps: 0,
+            ..LoopConfig::default()
+        };
+        assert!(invalid_field(config) == "max_catch_up_steps");
+    }
+
+    #[test]
+    fn unrepresentable_config() {
+        let invalid_field = |config: LoopConfig| match config.validate() {
+            Err(GerustError::InvalidLoopConfig { field, .. }) => field,
+            _ => "",
+        };
+
+        let config = LoopConfig {
+            update_rate: 1e-20,
+            ..LoopConfig::default()
+        };
+        assert!(invalid_field(config) == "update_rate");
+        let config = LoopConfig {
+            frame_rate_limit: Some(1e300),
+            ..LoopConfig::default()
+        };
+        assert!(invalid_field(config) == "frame_rate_limit");
+        let config = LoopConfig {
+            update_rate: 1e-10,
+            max_catch_up_steps: u32::MAX,
+            ..LoopConfig::default()
+        };
+        assert!(invalid_field(config) == "max_catch_up_steps");
+    }
+}
@@ -0,0 +1,328 @@
+use crate::{Commands, Component, Query, Res, Time, Transform, Vec2, Without};
+
+/// Position, rotation and scale of an entity, relative to its parent if it has one
+/// The Engine computes the matching GlobalTransform2D after every fixed update
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform2D {
+    pub translation: Vec2,
+    /// Counterclockwise rotation, in radians
+    pub rotation: f32,
+    pub scale: Vec2,
+}
+
+impl Default for Transform2D {
+    fn default() -> Transform2D {
+        Transform2D::IDENTITY
+    }
+}
+
+impl Transform2D {
+    /// Transform that leaves points untouched
+    pub const IDENTITY: Transform2D = Transform2D {
+        translation: Vec2::ZERO,
+        rotation: 0.,
+        scale: Vec2::ONE,
+    };
+
+    pub const fn from_translation(translation: Vec2) -> Transform2D {
+        Transform2D {
+            translation,
+            ..Transform2D::IDENTITY
+        }
+    }
+
+    pub const fn from_xy(x: f32, y: f32) -> Transform2D {
+        Transform2D::from_translation(Vec2::new(x, y))
+    }
+
+    pub fn with_rotation(self, rotation: f32) -> Transform2D {
+        Transform2D { rotation, ..self }
+    }
+
+    pub fn with_scale(self, scale: Vec2) -> Transform2D {
+        Transform2D { scale, ..self }
+    }
+
+    /// Move by offset
+    pub fn translate(&mut self, offset: Vec2) {
+        self.translation += offset;
+    }
+
+    /// Rotate counterclockwise by angle radians
+    pub fn rotate(&mut self, angle: f32) {
+        self.rotation += angle;
+    }
+
+    /// Same transform as an affine matrix
+    pub fn to_affine(&self) -> GlobalTransform2D {
+        let (sin, cos) = self.rotation.sin_cos();
+        GlobalTransform2D {
+            x_axis: Vec2::new(cos, sin) * self.scale.x,
+            y_axis: Vec2::new(-sin, cos) * self.scale.y,
+            translation: self.translation,
+        }
+    }
+
+    /// Scale, then rotate, then translate point
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        (point * self.scale).rotate(self.rotation) + self.translation
+    }
+
+    /// Transform applying child first, then self
+    /// A non-uniform scale of self combined with a rotation of child can't be
+    /// represented exactly, compose GlobalTransform2D to keep the shear
+    pub fn mul_transform(&self, child: &Transform2D) -> Transform2D {
+        self.to_affine().mul(&child.to_affine()).to_transform()
+    }
+
+    /// Transform undoing self, None if a scale is zero
+    pub fn inverse(&self) -> Option<Transform2D> {
+        self.to_affine()
+            .inverse()
+            .map(|inverse| inverse.to_transform())
+    }
+}
+
+impl Component for Transform2D {}
+
+impl Transform for Transform2D {
+    type Global = GlobalTransform2D;
+
+    fn to_global(&self) -> GlobalTransform2D {
+        self.to_affine()
+    }
+
+    fn compose(&self, parent: &GlobalTransform2D) -> GlobalTransform2D {
+        parent.mul(&self.to_affine())
+    }
+}
+
+/// Transform of an entity relative to the world, computed from its Transform2D and the ones of its ancestors
+/// Stored as an affine matrix, so shears created by nested scales and rotations are kept
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlobalTransform2D {
+    /// Image of the (1, 0) vector
+    pub x_axis: Vec2,
+    /// Image of the (0, 1) vector
+    pub y_axis: Vec2,
+    pub translation: Vec2,
+}
+
+impl Default for GlobalTransform2D {
+    fn default() -> GlobalTransform2D {
+        GlobalTransform2D::IDENTITY
+    }
+}
+
+impl GlobalTransform2D {
+    /// Transform that leaves points untouched
+    pub const IDENTITY: GlobalTransform2D = GlobalTransform2D {
+        x_axis: Vec2::X,
+        y_axis: Vec2::Y,
+        translation: Vec2::ZERO,
+    };
+
+    /// Transform applying other first, then self
+    pub fn mul(&self, other: &GlobalTransform2D) -> GlobalTransform2D {
+        GlobalTransform2D {
+            x_axis: self.transform_vector(other.x_axis),
+            y_axis: self.transform_vector(other.y_axis),
+            translation: self.transform_point(other.translation),
+        }
+    }
+
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        self.transform_vector(point) + self.translation
+    }
+
+    /// Transform a direction, ignoring the translation
+    pub fn transform_vector(&self, vector: Vec2) -> Vec2 {
+        self.x_axis * vector.x + self.y_axis * vector.y
+    }
+
+    /// Determinant of the linear part, negative if the transform mirrors
+    pub fn determinant(&self) -> f32 {
+        self.x_axis.perp_dot(self.y_axis)
+    }
+
+    /// Transform undoing self, None if it flattens the plane
+    pub fn inverse(&self) -> Option<GlobalTransform2D> {
+        let determinant = self.determinant();
+        if determinant == 0. {
+            return None;
+        }
+        let x_axis = Vec2::new(self.y_axis.y, -self.x_axis.y) / determinant;
+        let y_axis = Vec2::new(-self.y_axis.x, self.x_axis.x) / determinant;
+        let translation = -(x_axis * self.translation.x + y_axis * self.translation.y);
+        Some(GlobalTransform2D {
+            x_axis,
+            y_axis,
+            translation,
+        })
+    }
+
+    /// Rotation of the x axis, in radians
+    pub fn rotation(&self) -> f32 {
+        self.x_axis.angle()
+    }
+
+    /// Scale along the rotated axes, y is negative if the transform mirrors
+    pub fn scale(&self) -> Vec2 {
+        let x = self.x_axis.length();
+        if x == 0. {
+            return Vec2::new(0., self.y_axis.length());
+        }
+        Vec2::new(x, self.determinant() / x)
+    }
+
+    /// Decompose into translation, rotation and scale, dropping any shear
+    pub fn to_transform(&self) -> Transform2D {
+        Transform2D {
+            translation: self.translation,
+            rotation: self.rotation(),
+            scale: self.scale(),
+        }
+    }
+}
+
+impl From<Transform2D> for GlobalTransform2D {
+    fn from(transform: Transform2D) -> GlobalTransform2D {
+        transform.to_affine()
+    }
+}
+
+impl Component for GlobalTransform2D {}
+
+/// Linear velocity in units per second and angular velocity in radians per second,
+/// applied to the Transform2D of its entity by integrate_velocity
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Velocity2D {
+    pub linear: Vec2,
+    pub angular: f32,
+}
+
+impl Velocity2D {
+    pub const fn new(x: f32, y: f32) -> Velocity2D {
+        Velocity2D {
+            linear: Vec2::new(x, y),
+            angular: 0.,
+        }
+    }
+}
+
+impl Component for Velocity2D {}
+
+/// Move and rotate every entity with a Velocity2D by the time elapsed during the fixed update
+pub fn integrate_velocity(time: Res<Time>, mut query: Query<(&mut Transform2D, &Velocity2D)>) {
+    let delta = time.delta_secs();
+    for (_, (mut transform, velocity)) in query.iter_mut() {
+        // Resting entities keep their Transform2D unchanged for Changed filters
+        if *velocity != Velocity2D::default() {
+            transform.translation += velocity.linear * delta;
+            transform.rotation += velocity.angular * delta;
+        }
+    }
+}
+
+/// Give a GlobalTransform2D to every entity with a Transform2D but none yet
+pub fn insert_global_transforms(
+    query: Query<&Transform2D, Without<GlobalTransform2D>>,
+    commands: Commands,
+) {
+    for (entity, transform) in query.iter() {
+        commands.insert(entity, GlobalTransform2D::from(*transform));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Engine, EntityIndex};
+    use std::f32::consts::FRAC_PI_2;
+
+    fn approx_eq(vec1: Vec2, vec2: Vec2) -> bool {
+        vec1.distance(vec2) < 1e-4
+    }
+
+    #[test]
+    fn transform_point() {
+        let transform = Transform2D::from_xy(10., 0.)
+            .with_rotation(FRAC_PI_2)
+            .with_scale(Vec2::splat(2.));
+
+        let point = transform.transform_point(Vec2::new(1., 0.));
+
+        assert!(approx_eq(point, Vec2::new(10., 2.)));
+        assert!(approx_eq(
+            transform.to_affine().transform_point(Vec2::X),
+            point
+        ));
+    }
+
+    #[test]
+    fn compose_and_inverse() {
+        let parent = Transform2D::from_xy(5., 5.).with_rotation(FRAC_PI_2);
+        let child = Transform2D::from_xy(1., 0.).with_scale(Vec2::new(2., 3.));
+
+        let global = parent.mul_transform(&child);
+        let inverse = global.inverse().unwrap();
+
+        assert!(approx_eq(global.translation, Vec2::new(5., 6.)));
+        assert!((global.rotation - FRAC_PI_2).abs() < 1e-4);
+        assert!(approx_eq(global.scale, Vec2::new(2., 3.)));
+        let point = Vec2::new(3., -7.);
+        assert!(approx_eq(
+            inverse.transform_point(global.transform_point(point)),
+            point
+        ));
+        assert!(Transform2D::IDENTITY
+            .with_scale(Vec2::new(0., 1.))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn mirrored_scale() {
+        let transform = Transform2D::IDENTITY
+            .with_rotation(1.)
+            .with_scale(Vec2::new(2., -1.));
+
+        let decomposed = transform.to_affine().to_transform();
+
+        assert!(approx_eq(decomposed.scale, transform.scale));
+        assert!((decomposed.rotation - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn global_transforms() {
+        let mut engine = Engine::headless();
+        let parent = engine.create_entity();
+        let child = engine.create_entity();
+        engine.add_entity_component(parent, Transform2D::from_xy(10., 0.));
+        engine.add_entity_component(parent, Velocity2D::new(60., 0.));
+        engine.add_entity_component(child, Transform2D::from_xy(0., 5.));
+        engine.set_parent(child, parent);
+
+        engine.step_n(1).unwrap();
+
+        let globals = engine.query::<&GlobalTransform2D>();
+        let translation = |entity: EntityIndex| globals.get(entity).unwrap().translation;
+        assert!(approx_eq(translation(parent), Vec2::new(11., 0.)));
+        assert!(approx_eq(translation(child), Vec2::new(11., 5.)));
+    }
+
+    #[test]
+    fn resting_entities_unchanged() {
+        let mut engine = Engine::headless();
+        let entity = engine.create_entity();
+        engine.add_entity_component(entity, Transform2D::from_xy(10., 0.));
+        engine.add_entity_component(entity, Velocity2D::default());
+
+        engine.step_n(2).unwrap();
+
+        let transform = engine.component_ticks::<Transform2D>(entity).unwrap();
+        let global = engine.component_ticks::<GlobalTransform2D>(entity).unwrap();
+        assert!(transform.changed() == transform.added());
+        assert!(global.changed() == global.added());
+    }
+}
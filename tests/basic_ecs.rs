@@ -1,4 +1,5 @@
 use gerust::*;
+use sdl2::event::Event;
 
 #[derive(Debug, PartialEq, Eq)]
 struct Position {
@@ -15,24 +16,18 @@ impl Position {
 
 struct Gravity {}
 impl System for Gravity {
-    fn update(&self, engine: &Engine) {
-        let mask = engine.get_mask::<Position>();
-        let mut positions = engine.get_component::<Position>();
-
-        for (entity, _) in engine
-            .entities
-            .iter()
-            .filter(|(_, entity)| entity.components_mask() == mask)
-        {
-            positions.get_mut(entity.clone()).y -= 10;
-            println!("{:?}", positions.get(entity.clone()));
+    fn update(&self, engine: &Engine, _: &[Event]) -> Result<UpdateStatus, GerustError> {
+        for (_, mut position) in engine.query::<&mut Position>().iter_mut() {
+            position.y -= 10;
+            println!("{:?}", position);
         }
+        Ok(UpdateStatus::Continue)
     }
 }
 
 #[test]
 fn basic_ecs() {
-    let mut engine = Engine::default();
+    let mut engine = Engine::headless();
 
     engine.register_component::<Position>();
 
@@ -42,8 +37,21 @@ fn basic_ecs() {
     engine.register_system(Gravity {});
 
     for _ in 0..9 {
-        engine.update_ecs();
+        engine.step(&[]).unwrap();
     }
 
-    assert!(engine.get_component::<Position>().get(entity) == &Position::new(0, 10));
+    assert!(engine.get_component_ref::<Position>().get(entity) == &Position::new(0, 10));
+}
+
+#[test]
+fn step_n() {
+    let mut engine = Engine::headless();
+    engine.register_component::<Position>();
+    let entity = engine.create_entity();
+    engine.add_entity_component(entity, Position::new(0, 100));
+    engine.register_system(Gravity {});
+
+    engine.step_n(5).unwrap();
+
+    assert!(engine.get_component_ref::<Position>().get(entity) == &Position::new(0, 50));
 }